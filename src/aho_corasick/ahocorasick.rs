@@ -1,23 +1,72 @@
 use super::automaton::Automaton;
 use super::dfa::{self, Dfa};
-use super::nfa;
-use super::Match;
+use super::nfa::{self, Nfa};
+use super::{Match, Stats};
 
 #[derive(Clone)]
 pub struct AhoCorasick {
-    imp: Dfa,
+    imp: Imp,
+    stats: Stats,
+}
+
+/// The automaton that is searched with.
+#[derive(Clone)]
+enum Imp {
+    Nfa(Nfa),
+    Dfa(Dfa),
 }
 
 impl AhoCorasick {
     pub fn new<I, P>(patterns: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<[u8]>,
+    {
+        Self::with_limit(patterns, usize::MAX)
+    }
+
+    /// Build the automaton, searching with the DFA if it fits into `limit` bytes of heap along with
+    /// the NFA it is built from, and with the smaller but slower NFA otherwise.
+    pub fn with_limit<I, P>(patterns: I, limit: usize) -> Self
     where
         I: IntoIterator<Item = P>,
         P: AsRef<[u8]>,
     {
         let nfa = nfa::Nfa::new(patterns);
+        let mut stats = Stats {
+            nfa_states: nfa.state_len(),
+            nfa_heap_bytes: nfa.heap_bytes(),
+            ..Stats::default()
+        };
+
+        if nfa.heap_bytes().saturating_add(Dfa::estimate_heap_bytes(&nfa)) > limit {
+            stats.match_states = nfa.match_states();
+            return Self { imp: Imp::Nfa(nfa), stats };
+        }
+
         let dfa = dfa::Dfa::new(&nfa);
+        stats = Stats {
+            dfa: true,
+            dfa_states: dfa.state_count(),
+            match_states: dfa.match_states(),
+            max_match: dfa.max_match_index(),
+            dfa_heap_bytes: dfa.heap_bytes(),
+            ..stats
+        };
 
-        Self { imp: dfa }
+        Self { imp: Imp::Dfa(dfa), stats }
+    }
+
+    pub const fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Bytes allocated on the heap by the automaton that is searched with.
+    pub const fn heap_bytes(&self) -> usize {
+        match self.imp {
+            Imp::Nfa(_) => self.stats.nfa_heap_bytes,
+            Imp::Dfa(_) => self.stats.dfa_heap_bytes,
+        }
     }
 
     pub fn find_overlapping_iter<'a, 'b, B: ?Sized + AsRef<[u8]>>(
@@ -29,7 +78,7 @@ impl AhoCorasick {
 }
 
 pub struct FindIter<'a, 'b> {
-    fsm: &'a Dfa,
+    fsm: &'a Imp,
     haystack: &'b [u8],
     pos: usize,
     state_id: usize,
@@ -38,20 +87,27 @@ pub struct FindIter<'a, 'b> {
 
 impl<'a, 'b> FindIter<'a, 'b> {
     fn new(ac: &'a AhoCorasick, haystack: &'b [u8]) -> FindIter<'a, 'b> {
-        FindIter { fsm: &ac.imp, haystack, pos: 0, state_id: ac.imp.start_state(), match_index: 0 }
+        let state_id = match &ac.imp {
+            Imp::Nfa(nfa) => nfa.start_state(),
+            Imp::Dfa(dfa) => dfa.start_state(),
+        };
+        FindIter { fsm: &ac.imp, haystack, pos: 0, state_id, match_index: 0 }
     }
 }
 
-impl<'a, 'b> Iterator for FindIter<'a, 'b> {
+impl Iterator for FindIter<'_, '_> {
     type Item = Match;
 
     fn next(&mut self) -> Option<Match> {
-        let result = self.fsm.overlapping_find_at(
-            self.haystack,
-            self.pos,
-            &mut self.state_id,
-            &mut self.match_index,
-        );
+        let (state_id, match_index) = (&mut self.state_id, &mut self.match_index);
+        let result = match self.fsm {
+            Imp::Nfa(nfa) => {
+                nfa.overlapping_find_at(self.haystack, self.pos, state_id, match_index)
+            }
+            Imp::Dfa(dfa) => {
+                dfa.overlapping_find_at(self.haystack, self.pos, state_id, match_index)
+            }
+        };
         match result {
             None => None,
             Some(m) => {
@@ -26,7 +26,7 @@ pub trait Automaton {
 
     #[inline]
     fn standard_find_at(&self, haystack: &[u8], at: usize, state_id: &mut usize) -> Option<Match> {
-        assert!(self.is_valid(*state_id), "{} is not a valid state ID", state_id);
+        assert!(self.is_valid(*state_id), "{state_id} is not a valid state ID");
         let mut at = at;
         while at < haystack.len() {
             *state_id = self.next_state_no_fail(*state_id, haystack[at]);
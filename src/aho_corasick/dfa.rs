@@ -1,3 +1,5 @@
+use std::mem::size_of;
+
 use super::automaton::Automaton;
 use super::nfa::{Nfa, START_ID};
 use super::state_id::{DEAD_ID, FAIL_ID};
@@ -82,6 +84,39 @@ impl Dfa {
         dfa
     }
 
+    /// Bytes the DFA built from the NFA will allocate on the heap, see [`Dfa::heap_bytes`].
+    pub fn estimate_heap_bytes(nfa: &Nfa) -> usize {
+        let states = nfa.state_len();
+        states * (ALPHABET_LEN * size_of::<usize>() + size_of::<Vec<(usize, usize)>>())
+            + (0..states).map(|id| nfa.matches(id).len()).sum::<usize>()
+                * size_of::<(usize, usize)>()
+    }
+
+    pub const fn state_count(&self) -> usize {
+        self.state_count
+    }
+
+    /// The index of the highest of all match states, which come right after the dead state. Unlike
+    /// its ID, the index isn't premultiplied.
+    pub const fn max_match_index(&self) -> usize {
+        if self.premultiplied {
+            self.max_match / ALPHABET_LEN
+        } else {
+            self.max_match
+        }
+    }
+
+    pub const fn match_states(&self) -> usize {
+        self.max_match_index().saturating_sub(DEAD_ID)
+    }
+
+    /// Bytes allocated on the heap for the transition table and the matches of each state.
+    pub fn heap_bytes(&self) -> usize {
+        self.trans.capacity() * size_of::<usize>()
+            + self.matches.capacity() * size_of::<Vec<(usize, usize)>>()
+            + self.matches.iter().map(|m| m.capacity() * size_of::<(usize, usize)>()).sum::<usize>()
+    }
+
     const fn is_match_state(&self, id: usize) -> bool {
         id <= self.max_match && id > DEAD_ID
     }
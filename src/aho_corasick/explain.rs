@@ -0,0 +1,184 @@
+//! Rendering the automata of a set of patterns, to find out why words match or don't.
+
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+use super::automaton::Automaton;
+use super::dfa::{Dfa, ALPHABET_LEN};
+use super::nfa::{Nfa, START_ID};
+use super::state_id::{DEAD_ID, FAIL_ID};
+
+/// Write the NFA of the patterns as Graphviz DOT graph with the given name. Match states list all
+/// patterns they match, goto edges are solid and failure links are dashed. The loop of the start
+/// state on every byte that doesn't start a pattern is left out.
+pub fn write_dot<P: AsRef<[u8]>>(
+    name: &str,
+    patterns: &[P],
+    mut out: impl Write,
+) -> io::Result<()> {
+    let nfa = Nfa::new(patterns);
+
+    writeln!(out, "digraph {name} {{")?;
+    writeln!(out, "    rankdir=LR;")?;
+    writeln!(out, "    node [shape=circle];")?;
+
+    for id in START_ID..nfa.state_len() {
+        let matches = nfa.matches(id);
+        if matches.is_empty() {
+            writeln!(out, "    {id};")?;
+        } else {
+            let labels: Vec<_> =
+                matches.iter().map(|&(p, _)| label(patterns[p].as_ref())).collect();
+            writeln!(
+                out,
+                "    {id} [shape=doublecircle, label=\"{id}\\n{}\"];",
+                labels.join("\\n")
+            )?;
+        }
+    }
+
+    for id in START_ID..nfa.state_len() {
+        let mut edges = vec![];
+        nfa.iter_all_transitions(id, |b, next| {
+            if next != FAIL_ID && (id != START_ID || next != START_ID) {
+                edges.push((b, next));
+            }
+        });
+
+        for (b, next) in edges {
+            writeln!(out, "    {id} -> {next} [label=\"{}\"];", label(&[b]))?;
+        }
+        if id != START_ID {
+            let fail = nfa.failure_transition(id);
+            writeln!(out, "    {id} -> {fail} [style=dashed, color=red, constraint=false];")?;
+        }
+    }
+
+    writeln!(out, "}}")
+}
+
+/// Write the DFA transition table of the patterns, one line per state. Runs of bytes that lead to
+/// the same state are collapsed into ranges of hexadecimal bytes. States are given by their index,
+/// rather than the premultiplied IDs used while searching, and match states list their patterns.
+pub fn write_dfa_table<P: AsRef<[u8]>>(
+    name: &str,
+    patterns: &[P],
+    mut out: impl Write,
+) -> io::Result<()> {
+    let dfa = Dfa::new(&Nfa::new(patterns));
+    let index = |id: usize| if id == DEAD_ID { DEAD_ID } else { id / ALPHABET_LEN };
+
+    writeln!(
+        out,
+        "{name}: {} states, start {}, max_match {}",
+        dfa.state_count(),
+        index(dfa.start_state()),
+        dfa.max_match_index()
+    )?;
+
+    for state in START_ID..dfa.state_count() {
+        let id = state * ALPHABET_LEN;
+        write!(out, "{state}")?;
+
+        let matches: Vec<_> = (0..dfa.match_count(id))
+            .filter_map(|i| dfa.get_match(id, i, 0))
+            .map(|m| format!("{} \"{}\"", m.pattern(), label(patterns[m.pattern()].as_ref())))
+            .collect();
+        if !matches.is_empty() {
+            write!(out, " [{}]", matches.join(", "))?;
+        }
+        write!(out, ":")?;
+
+        let mut runs: Vec<(u8, u8, usize)> = vec![];
+        for b in 0..=255 {
+            let next = index(dfa.next_state(id, b));
+            match runs.last_mut() {
+                Some((_, last, target)) if *target == next => *last = b,
+                _ => runs.push((b, b, next)),
+            }
+        }
+        for (first, last, next) in runs {
+            if first == last {
+                write!(out, " {first:02x}>{next}")?;
+            } else {
+                write!(out, " {first:02x}-{last:02x}>{next}")?;
+            }
+        }
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
+
+/// Text for a label within double quotes. Control characters and bytes that aren't valid UTF-8
+/// are written as hex escapes.
+fn label(bytes: &[u8]) -> String {
+    let mut s = String::new();
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '"' | '\\' => {
+                    s.push('\\');
+                    s.push(c);
+                }
+                _ if c.is_control() => write!(s, "\\\\x{:02x}", u32::from(c)).unwrap(),
+                _ => s.push(c),
+            }
+        }
+        for b in chunk.invalid() {
+            write!(s, "\\\\x{b:02x}").unwrap();
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Patterns sharing a state, with a quote and a byte that isn't valid UTF-8 to escape.
+    const PATTERNS: [&[u8]; 3] = [b"ab", b"b\"", b"\xff"];
+
+    #[test]
+    fn dot() {
+        let expected = r#"digraph words {
+    rankdir=LR;
+    node [shape=circle];
+    2;
+    3;
+    4 [shape=doublecircle, label="4\nab"];
+    5;
+    6 [shape=doublecircle, label="6\nb\""];
+    7 [shape=doublecircle, label="7\n\\xff"];
+    2 -> 3 [label="a"];
+    2 -> 5 [label="b"];
+    2 -> 7 [label="\\xff"];
+    3 -> 4 [label="b"];
+    3 -> 2 [style=dashed, color=red, constraint=false];
+    4 -> 5 [style=dashed, color=red, constraint=false];
+    5 -> 6 [label="\""];
+    5 -> 2 [style=dashed, color=red, constraint=false];
+    6 -> 2 [style=dashed, color=red, constraint=false];
+    7 -> 2 [style=dashed, color=red, constraint=false];
+}
+"#;
+        let mut out = vec![];
+        write_dot("words", &PATTERNS, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn dfa_table() {
+        let expected = r#"words: 8 states, start 7, max_match 4
+2 [2 "\\xff"]: 00-60>7 61>6 62>5 63-fe>7 ff>2
+3 [1 "b\""]: 00-60>7 61>6 62>5 63-fe>7 ff>2
+4 [0 "ab"]: 00-21>7 22>3 23-60>7 61>6 62>5 63-fe>7 ff>2
+5: 00-21>7 22>3 23-60>7 61>6 62>5 63-fe>7 ff>2
+6: 00-60>7 61>6 62>4 63-fe>7 ff>2
+7: 00-60>7 61>6 62>5 63-fe>7 ff>2
+"#;
+        let mut out = vec![];
+        write_dfa_table("words", &PATTERNS, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+}
@@ -1,13 +1,33 @@
 #![allow(clippy::similar_names, clippy::module_name_repetitions, clippy::cast_possible_truncation)]
 
-pub use ahocorasick::{AhoCorasick, FindIter};
+use serde::Serialize;
+
+pub use ahocorasick::AhoCorasick;
+pub use explain::{write_dfa_table, write_dot};
 
 mod ahocorasick;
 mod automaton;
 mod dfa;
+mod explain;
 mod nfa;
 mod state_id;
 
+/// Sizes of an automaton, to see what a set of patterns costs. The NFA is only used to build the
+/// DFA and dropped afterwards, unless the DFA doesn't fit into the memory limit and the NFA is
+/// searched instead, in which case all DFA sizes are zero.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct Stats {
+    /// Whether the DFA is searched.
+    pub dfa: bool,
+    pub nfa_states: usize,
+    pub nfa_heap_bytes: usize,
+    pub dfa_states: usize,
+    pub match_states: usize,
+    /// The index of the last match state within the DFA.
+    pub max_match: usize,
+    pub dfa_heap_bytes: usize,
+}
+
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct Match {
     pattern: usize,
@@ -21,6 +41,11 @@ impl Match {
         self.pattern
     }
 
+    #[inline]
+    pub const fn start(&self) -> usize {
+        self.end - self.len
+    }
+
     #[inline]
     pub const fn end(&self) -> usize {
         self.end
@@ -1,7 +1,10 @@
 use std::collections::{BTreeSet, VecDeque};
+use std::mem::size_of;
 use std::ops::{Index, IndexMut};
 
+use super::automaton::Automaton;
 use super::state_id::{DEAD_ID, FAIL_ID};
+use super::Match;
 
 pub const START_ID: usize = 2;
 
@@ -10,6 +13,41 @@ pub struct Nfa {
     states: Vec<State>,
 }
 
+/// Searching with the NFA follows the failure links, which is slower than the DFA, but only needs
+/// the transitions of the trie.
+impl Automaton for Nfa {
+    fn start_state(&self) -> usize {
+        START_ID
+    }
+
+    fn is_valid(&self, id: usize) -> bool {
+        id < self.states.len()
+    }
+
+    fn is_match_state(&self, id: usize) -> bool {
+        self.states[id].is_match()
+    }
+
+    fn get_match(&self, id: usize, match_index: usize, end: usize) -> Option<Match> {
+        self.states[id].matches.get(match_index).map(|&(pattern, len)| Match { pattern, len, end })
+    }
+
+    fn match_count(&self, id: usize) -> usize {
+        self.states[id].matches.len()
+    }
+
+    fn next_state(&self, mut current: usize, input: u8) -> usize {
+        // The start state has a transition for every byte, so this always ends.
+        loop {
+            let next = self.states[current].next_state(input);
+            if next != FAIL_ID {
+                return next;
+            }
+            current = self.states[current].fail;
+        }
+    }
+}
+
 impl Nfa {
     pub fn new<I, P>(patterns: I) -> Self
     where
@@ -23,6 +61,16 @@ impl Nfa {
         self.states.len()
     }
 
+    /// Bytes allocated on the heap for the states, their transitions and matches.
+    pub fn heap_bytes(&self) -> usize {
+        self.states.capacity() * size_of::<State>()
+            + self.states.iter().map(State::heap_bytes).sum::<usize>()
+    }
+
+    pub fn match_states(&self) -> usize {
+        self.states.iter().filter(|state| state.is_match()).count()
+    }
+
     pub fn matches(&self, id: usize) -> &[(usize, usize)] {
         &self.states[id].matches
     }
@@ -99,6 +147,10 @@ impl State {
         !self.matches.is_empty()
     }
 
+    fn heap_bytes(&self) -> usize {
+        self.trans.heap_bytes() + self.matches.capacity() * size_of::<(usize, usize)>()
+    }
+
     fn next_state(&self, input: u8) -> usize {
         self.trans.next_state(input)
     }
@@ -173,6 +225,13 @@ impl Transitions {
         }
     }
 
+    fn heap_bytes(&self) -> usize {
+        match *self {
+            Self::Sparse(ref sparse) => sparse.capacity() * size_of::<(u8, usize)>(),
+            Self::Dense(ref dense) => dense.0.capacity() * size_of::<usize>(),
+        }
+    }
+
     fn iter_all<F: FnMut(u8, usize)>(&self, mut f: F) {
         match *self {
             Self::Sparse(ref sparse) => {
@@ -203,7 +262,7 @@ impl<'a> IterTransitionsMut<'a> {
     }
 }
 
-impl<'a> Iterator for IterTransitionsMut<'a> {
+impl Iterator for IterTransitionsMut<'_> {
     type Item = (u8, usize);
 
     fn next(&mut self) -> Option<(u8, usize)> {
@@ -380,7 +439,7 @@ fn sparse_iter<F: FnMut(u8, usize)>(trans: &[(u8, usize)], mut f: F) {
 }
 
 fn get_two_mut<T>(xs: &mut [T], i: usize, j: usize) -> (&mut T, &mut T) {
-    assert!(i != j, "{} must not be equal to {}", i, j);
+    assert!(i != j, "{i} must not be equal to {j}");
     if i < j {
         let (before, after) = xs.split_at_mut(j);
         (&mut before[i], &mut after[0])
@@ -2,85 +2,562 @@
 //!
 //! [aho_corasick_wiki]: https://en.wikipedia.org/wiki/Aho%E2%80%93Corasick_algorithm
 
-use std::io::{BufRead, BufReader, Read, Result};
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::Write;
+use std::io::{self, BufReader, Read};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
 
-use crate::aho_corasick::AhoCorasick;
+use crate::aho_corasick::{self, AhoCorasick};
+use crate::checkpoint::Checkpoint;
+use crate::chunks::{Chunk, Chunks};
+use crate::cooccurrence::{Matrix, Pairs, Window};
+use crate::fold::{Folded, Folding};
+use crate::fuzzy::FuzzySearcher;
+use crate::input::{Groups, Offsets};
+use crate::memory::{self, Budget, OutOfMemory};
+use crate::query::Query;
+use crate::stats::{self, Recorder, Stats};
+use crate::units::{Unit, UnitReport, Units};
+use crate::words::{self, Kind, Word};
 use indexmap::map::IndexMap;
-use rayon::prelude::*;
+use regex::{Regex, RegexBuilder, RegexSet};
 
-/// The maximum lines to chunk together into a single string. This value showed the best results for
-/// the input given during the challenge.
-const LINE_LIMIT: u64 = 10000;
+/// Options for processing the article.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Settings<'a> {
+    pub folding: Folding,
+    /// Print the byte range of every match within the original article.
+    pub positions: bool,
+    /// Map positions within the converted article back to the original one.
+    pub offsets: Option<&'a Offsets>,
+    /// Collect the concrete strings that matched each wildcard.
+    pub expand: bool,
+    /// Additionally count occurrences of literal words within this many edits.
+    pub max_edits: Option<usize>,
+    /// Count pairs of words found within this window of each other.
+    pub cooccurrence: Option<Window>,
+    /// Count per line, paragraph or document.
+    pub unit: Option<&'a Unit>,
+    /// Count the units that satisfy each of these queries.
+    pub queries: &'a [Query],
+    /// Keep the counts of every unit for a term-document matrix.
+    pub term_matrix: bool,
+    /// Count per group of records.
+    pub groups: Option<&'a Groups>,
+    /// Save the counts of the chunks that are done from time to time.
+    pub checkpoint: Option<&'a Checkpoint>,
+    /// Bytes at the start of the article that were counted before resuming. They are only read
+    /// for the text before the matches after them.
+    pub lead_in: usize,
+    /// Size of the chunks that are scanned in parallel, in bytes.
+    pub chunk_size: u64,
+    /// Keep the automata and the chunks being scanned within this memory.
+    pub budget: Option<&'a Budget>,
+    /// End the scan before the end of the article.
+    pub stop: Option<EarlyStop>,
+}
 
-pub fn process<R>(words: &IndexMap<&str, AtomicU64>, article: BufReader<R>)
-where
-    R: Read + Send,
-{
-    let patterns: Vec<_> = words.keys().collect();
+/// When to end the scan early.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EarlyStop {
+    /// Stop counting each word at this count, and end the scan once all words reached it.
+    At(u64),
+    /// End the scan as soon as any word is found.
+    Any,
+}
 
-    // Prepare the automaton.
-    let ac = AhoCorasick::new(&patterns);
+/// Additional findings besides the counts.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// For each word, how often each concrete string matched it. Only filled for wildcards and if
+    /// enabled in the [`Settings`].
+    pub expansions: Vec<HashMap<String, u64>>,
+    /// For each word, the amount of approximate but not exact matches. Only filled if enabled in
+    /// the [`Settings`].
+    pub fuzzy: Vec<u64>,
+    /// How often each pair of words was found near each other. Only filled if enabled in the
+    /// [`Settings`].
+    pub cooccurrence: Pairs,
+    /// Counts per unit. Only filled if enabled in the [`Settings`].
+    pub units: Option<UnitReport>,
+    /// Statistics of the run.
+    pub stats: Option<Stats>,
+}
 
-    // Run the automaton on every line separately on multiple
-    // threads to improve throughput.
-    chunked_lines(article, LINE_LIMIT).par_bridge().map(Result::unwrap).for_each(|line| {
-        for mat in ac.find_overlapping_iter(&line) {
-            words.get_index(mat.pattern()).unwrap().1.fetch_add(1, Ordering::SeqCst);
-        }
-    });
+/// The automata for all words of the list.
+struct Automata {
+    /// Literal words that match case-sensitively.
+    plain: Searcher,
+    /// Literal words that match case-insensitively, searched within case folded text.
+    folded: Searcher,
+    regexes: RegexSearcher,
+    /// Approximate matches of the literal words, if enabled.
+    plain_fuzzy: Option<FuzzySearcher>,
+    folded_fuzzy: Option<FuzzySearcher>,
+}
+
+impl Automata {
+    fn new(
+        words: &IndexMap<&Word<'_>, AtomicU64>,
+        settings: &Settings<'_>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let Settings { folding, max_edits, budget, .. } = *settings;
+        let case_folding = folding.with_case();
+        let (plain, folded, regexes) = partition(words, folding);
+        let fuzzy = |ids: &[usize], folding| {
+            max_edits.map(|k| fuzzy_searcher(words, ids, folding, k, budget)).transpose()
+        };
+
+        Ok(Self {
+            plain_fuzzy: fuzzy(&plain, folding)?,
+            folded_fuzzy: fuzzy(&folded, case_folding)?,
+            plain: Searcher::new(words, plain, folding, budget)?,
+            folded: Searcher::new(words, folded, case_folding, budget)?,
+            regexes: RegexSearcher::new(words, regexes, folding)?,
+        })
+    }
+
+    fn overlap(&self, max_edits: Option<usize>) -> usize {
+        overlap(self.plain.longest.max(self.folded.longest), max_edits)
+    }
+
+    /// Whether chunks must end after a line break, as no overlap is long enough for a regular
+    /// expression, but most of them stay within a line.
+    fn whole_lines(&self) -> bool {
+        !self.regexes.ids.is_empty()
+    }
+
+    /// How many copies of a chunk exist while it is scanned: the chunk itself and, unless folding
+    /// leaves the text as it is, the folded text for each automaton.
+    fn copies(&self, folding: Folding) -> u64 {
+        1 + u64::from(!folding.is_identity()) + u64::from(!self.folded.is_empty())
+    }
+
+    fn stats(&self) -> Vec<stats::Automaton> {
+        vec![self.plain.stats("plain"), self.folded.stats("folded")]
+    }
 }
 
-struct ChunkedLines<B> {
-    buf: B,
-    limit: u64,
+/// How many bytes chunks must overlap, so every match of a literal word of up to `longest` bytes
+/// lies completely within one of them. Folding can turn a single character of the article into
+/// several, so every byte of a word is allowed four bytes of article, the longest a UTF-8
+/// character gets, plus a character before the match for checking whole words.
+fn overlap(longest: usize, max_edits: Option<usize>) -> usize {
+    4 * (longest + max_edits.unwrap_or_default() + 1)
 }
 
-/// Similar to [`lines`] returns an iterator over the lines of a reader, but instead of
-/// iterating over each line they are chunked together into a single string until the `limit` or EOL
-/// is reached.
-///
-/// Also,  in contrast to [`lines`] each string returned *will contain* the newline bytes and CRLFs.
+/// How many bytes of the article before a position a scan has to read as well, so it finds the
+/// same matches from there on as a scan of the whole article. This is the overlap of the chunks.
+pub fn lead_in(words: &IndexMap<&Word<'_>, AtomicU64>, folding: Folding) -> usize {
+    let (plain, folded, _) = partition(words, folding);
+    let longest = |ids: Vec<usize>, folding: Folding| {
+        ids.into_iter().map(|i| folding.fold(&words.get_index(i).unwrap().0.text).len()).max()
+    };
+    let longest = longest(plain, folding).max(longest(folded, folding.with_case()));
+    overlap(longest.unwrap_or_default(), None)
+}
+
+/// Total count of all words.
+fn total(words: &IndexMap<&Word<'_>, AtomicU64>) -> u64 {
+    words.values().map(|count| count.load(Ordering::SeqCst)).sum()
+}
+
+/// Count all words in the article.
 ///
-/// [`lines`]: https://doc.rust-lang.org/std/io/trait.BufRead.html#method.lines
-const fn chunked_lines<R>(buf: R, limit: u64) -> ChunkedLines<R>
+/// Literal words are searched with Aho-Corasick, which counts overlapping matches. Regular
+/// expressions and wildcards are counted independently of all other words, but their own matches
+/// don't overlap. Empty matches are never counted.
+pub fn process<R>(
+    words: &IndexMap<&Word<'_>, AtomicU64>,
+    article: BufReader<R>,
+    settings: Settings<'_>,
+) -> Result<Report, Box<dyn Error>>
 where
-    R: BufRead + Sized,
+    R: Read + Send,
 {
-    ChunkedLines { buf, limit }
+    let mut recorder = Recorder::new(total(words));
+    let automata = Automata::new(words, &settings)?;
+    recorder.built();
+
+    let scan = Scan {
+        words,
+        settings,
+        expansions: Mutex::new(vec![HashMap::new(); words.len()]),
+        fuzzy: (0..words.len()).map(|_| AtomicU64::default()).collect(),
+        matrix: settings.cooccurrence.map(Matrix::new),
+        units: settings
+            .unit
+            .map(|unit| Units::new(unit, settings.queries, words.len(), settings.term_matrix)),
+        automata,
+        recorder,
+        saturation: Saturation::new(words, settings.stop),
+    };
+
+    // Run the automata on every chunk separately on multiple threads to improve throughput, while
+    // another thread reads the next chunks.
+    let size = usize::try_from(settings.chunk_size.max(1)).unwrap_or(usize::MAX);
+    let mut chunks = Chunks::new(article, size, scan.automata.overlap(settings.max_edits))
+        .lead_in(settings.lead_in)
+        .whole_lines(settings.unit.is_some() || scan.automata.whole_lines());
+    if let Some(budget) = settings.budget {
+        let bytes = chunks.buffer_size() as u64 * scan.automata.copies(settings.folding);
+        let buffers = memory::buffers(budget, chunks.buffers(), bytes)?;
+        chunks = chunks.limit(buffers);
+    }
+    chunks.read_ahead(|chunk| {
+        scan.chunk(chunk);
+        Ok(!scan.saturation.is_done())
+    })?;
+
+    Ok(scan.finish())
+}
+
+/// Everything the worker threads share while scanning.
+struct Scan<'a, 'w> {
+    words: &'a IndexMap<&'a Word<'w>, AtomicU64>,
+    settings: Settings<'a>,
+    automata: Automata,
+    expansions: Mutex<Vec<HashMap<String, u64>>>,
+    fuzzy: Vec<AtomicU64>,
+    matrix: Option<Matrix>,
+    units: Option<Units<'a>>,
+    recorder: Recorder,
+    saturation: Saturation,
 }
 
-impl<B: BufRead> Iterator for ChunkedLines<B> {
-    type Item = Result<String>;
+impl Scan<'_, '_> {
+    /// Count the words within a single chunk.
+    fn chunk(&self, chunk: &Chunk) {
+        let Settings { folding, positions, offsets, expand, groups, checkpoint, .. } =
+            self.settings;
+        if self.saturation.is_done() {
+            return;
+        }
+
+        let started = Instant::now();
+        let mut found = String::new();
+        let mut expanded = HashMap::<_, u64>::new();
+        let mut matched = vec![];
+        let mut count = |id: usize, text: &Folded<'_>, start: usize, end: usize| {
+            let (word, count) = self.words.get_index(id).unwrap();
+            if start == end
+                || (word.flags.whole_word && !words::is_whole_word(text.text(), start, end))
+            {
+                return;
+            }
+
+            let (start, end) = (text.original_start(start), text.original_end(end));
+            if !chunk.owns(start) || !self.saturation.add(count) {
+                return;
+            }
+
+            if positions {
+                let (start, end) = (chunk.offset + start as u64, chunk.offset + end as u64);
+                let (start, end) = offsets.map_or((start, end), |o| o.original(start, end));
+                writeln!(found, "{start}\t{end}\t{word}").unwrap();
+            }
+
+            if expand && word.kind == Kind::Glob {
+                *expanded.entry((id, &chunk.text[start..end])).or_default() += 1;
+            }
+
+            if self.matrix.is_some()
+                || self.units.is_some()
+                || groups.is_some()
+                || checkpoint.is_some()
+            {
+                matched.push((chunk.own_position(start), id));
+            }
+        };
+
+        let text = folding.fold_mapped(&chunk.text);
+        self.automata.plain.find(&text, &mut count);
+        self.automata.regexes.find(&text, &mut count);
+        self.find_fuzzy(self.automata.plain_fuzzy.as_ref(), chunk, &text);
+
+        if !self.automata.folded.is_empty() && !self.saturation.is_done() {
+            let text = folding.with_case().fold_mapped(&chunk.text);
+            self.automata.folded.find(&text, &mut count);
+            self.find_fuzzy(self.automata.folded_fuzzy.as_ref(), chunk, &text);
+        }
+
+        // Print all positions of a chunk at once, to keep them
+        // from interleaving with other threads.
+        if !found.is_empty() {
+            print!("{found}");
+        }
+
+        if !expanded.is_empty() {
+            let mut expansions = self.expansions.lock().unwrap();
+            for ((id, s), n) in expanded {
+                *expansions[id].entry(s.to_owned()).or_default() += n;
+            }
+        }
+
+        // The side tables only get the part of the chunk that no other chunk covers.
+        let (offset, own) = chunk.own();
+
+        if let Some(units) = &self.units {
+            matched.sort_unstable();
+            units.add(offset, own, &matched);
+        }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let mut buf = String::new();
-        let mut lines = 0;
+        if let Some(groups) = groups {
+            groups.add(offset, &matched);
+        }
+
+        if let Some(checkpoint) = checkpoint {
+            checkpoint.add(offset, own.len() as u64, &matched);
+        }
+
+        if let Some(matrix) = &self.matrix {
+            matrix.add(chunk, matched);
+        }
+
+        self.recorder.chunk(own.len(), started);
+    }
 
-        loop {
-            match self.buf.read_line(&mut buf) {
-                // Finish on EOL. If the buffer has remaining data,
-                // return it first and finish in the next call.
-                Ok(0) => {
-                    if buf.is_empty() {
-                        return None;
-                    }
-                    break;
+    fn find_fuzzy(&self, fuzzy: Option<&FuzzySearcher>, chunk: &Chunk, text: &Folded<'_>) {
+        if let Some(fuzzy) = fuzzy {
+            fuzzy.find(text.text().as_bytes(), |id, end| {
+                if chunk.owns_end(text.original_end(end)) {
+                    self.fuzzy[id].fetch_add(1, Ordering::SeqCst);
                 }
-                // Collect lines until we reach the limit.
-                Ok(_) => {
-                    lines += 1;
-                    if lines > self.limit {
-                        break;
-                    }
+            });
+        }
+    }
+
+    fn finish(self) -> Report {
+        Report {
+            expansions: self.expansions.into_inner().unwrap(),
+            fuzzy: if self.settings.max_edits.is_some() {
+                self.fuzzy.into_iter().map(AtomicU64::into_inner).collect()
+            } else {
+                vec![]
+            },
+            cooccurrence: self.matrix.map(Matrix::finish).unwrap_or_default(),
+            units: self.units.map(Units::finish),
+            stats: Some(self.recorder.finish(self.automata.stats(), total(self.words))),
+        }
+    }
+}
+
+/// Which words reached the count to stop at, to end the scan once enough of them did.
+struct Saturation {
+    /// Stop counting each word at this count.
+    limit: Option<u64>,
+    /// Number of words that reached the limit.
+    saturated: AtomicUsize,
+    /// End the scan once this many words reached the limit.
+    needed: usize,
+}
+
+impl Saturation {
+    fn new(words: &IndexMap<&Word<'_>, AtomicU64>, stop: Option<EarlyStop>) -> Self {
+        let limit = match stop {
+            Some(EarlyStop::At(limit)) => Some(limit),
+            Some(EarlyStop::Any) => Some(1),
+            None => None,
+        };
+        // Empty words are never searched, so they can't reach the limit. Counts resumed from a
+        // checkpoint may have reached it already.
+        let searched = || words.iter().filter(|(word, _)| !word.text.is_empty());
+        let saturated = limit.map_or(0, |limit| {
+            searched().filter(|(_, count)| count.load(Ordering::SeqCst) >= limit).count()
+        });
+        let needed = if stop == Some(EarlyStop::Any) { 1 } else { searched().count() };
+        Self { limit, saturated: AtomicUsize::new(saturated), needed }
+    }
+
+    /// Count a match of a word, unless the word reached the limit already.
+    fn add(&self, count: &AtomicU64) -> bool {
+        let Some(limit) = self.limit else {
+            count.fetch_add(1, Ordering::SeqCst);
+            return true;
+        };
+
+        match count
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| (n < limit).then_some(n + 1))
+        {
+            Ok(n) => {
+                if n + 1 == limit {
+                    self.saturated.fetch_add(1, Ordering::SeqCst);
                 }
-                // Report back any errors of read calls.
-                Err(e) => return Some(Err(e)),
+                true
             }
+            Err(_) => false,
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.limit.is_some() && self.saturated.load(Ordering::SeqCst) >= self.needed
+    }
+}
+
+/// Split the words into plain literals, literals that are searched case folded and regular
+/// expressions, returning their indices.
+///
+/// Words that ignore case are searched in a case folded copy of the article, unless the whole
+/// article is case folded anyway. Regular expressions handle case insensitivity themselves.
+fn partition(
+    words: &IndexMap<&Word<'_>, AtomicU64>,
+    folding: Folding,
+) -> (Vec<usize>, Vec<usize>, Vec<usize>) {
+    let case_folding = folding.with_case();
+    let mut plain = vec![];
+    let mut folded = vec![];
+    let mut regexes = vec![];
+
+    for (i, w) in words.keys().enumerate() {
+        match w.kind {
+            // Empty words would match at every single position, so they're left out entirely.
+            _ if w.text.is_empty() => {}
+            Kind::Regex | Kind::Glob => regexes.push(i),
+            Kind::Literal if w.flags.ignore_case && case_folding != folding => folded.push(i),
+            Kind::Literal => plain.push(i),
         }
+    }
+
+    (plain, folded, regexes)
+}
 
-        // Either we hit the line limit or reached the end and have
-        // remaining data in the buffer.
-        Some(Ok(buf))
+/// Write the automata of the literal words as Graphviz DOT graphs, or their DFA transition tables.
+/// Words that match case-insensitively get an automaton of their own, which is only written if
+/// there are any.
+pub fn explain(
+    words: &IndexMap<&Word<'_>, AtomicU64>,
+    folding: Folding,
+    dfa: bool,
+    mut out: impl io::Write,
+) -> io::Result<()> {
+    let (plain, folded, _) = partition(words, folding);
+    let automata = [("plain", plain, folding), ("folded", folded, folding.with_case())];
+
+    for (i, (name, ids, folding)) in automata.into_iter().enumerate() {
+        if i > 0 && ids.is_empty() {
+            continue;
+        }
+
+        let patterns: Vec<_> =
+            ids.iter().map(|&i| folding.fold(&words.get_index(i).unwrap().0.text)).collect();
+        if dfa {
+            aho_corasick::write_dfa_table(name, &patterns, &mut out)?;
+        } else {
+            aho_corasick::write_dot(name, &patterns, &mut out)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// A searcher for approximate matches of the literal words with the same folding.
+fn fuzzy_searcher(
+    words: &IndexMap<&Word<'_>, AtomicU64>,
+    ids: &[usize],
+    folding: Folding,
+    max_edits: usize,
+    budget: Option<&Budget>,
+) -> Result<FuzzySearcher, OutOfMemory> {
+    let patterns =
+        ids.iter().map(|&i| (i, folding.fold(&words.get_index(i).unwrap().0.text).into()));
+    FuzzySearcher::new(patterns, max_edits, budget)
+}
+
+/// An automaton over the literal words with the same folding.
+struct Searcher {
+    ac: AhoCorasick,
+    /// Index within the words map for each pattern of the automaton.
+    ids: Vec<usize>,
+    /// Length of the longest pattern in bytes.
+    longest: usize,
+}
+
+impl Searcher {
+    fn new(
+        words: &IndexMap<&Word<'_>, AtomicU64>,
+        ids: Vec<usize>,
+        folding: Folding,
+        budget: Option<&Budget>,
+    ) -> Result<Self, OutOfMemory> {
+        let patterns: Vec<_> =
+            ids.iter().map(|&i| folding.fold(&words.get_index(i).unwrap().0.text)).collect();
+
+        let longest = patterns.iter().map(String::len).max().unwrap_or_default();
+        Ok(Self { ac: memory::automaton(&patterns, budget)?, ids, longest })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    fn stats(&self, name: &'static str) -> stats::Automaton {
+        stats::Automaton { name, patterns: self.ids.len(), sizes: self.ac.stats() }
+    }
+
+    fn find(&self, text: &Folded<'_>, mut f: impl FnMut(usize, &Folded<'_>, usize, usize)) {
+        if self.is_empty() {
+            return;
+        }
+
+        for mat in self.ac.find_overlapping_iter(text.text()) {
+            f(self.ids[mat.pattern()], text, mat.start(), mat.end());
+        }
+    }
+}
+
+/// All regular expressions, combined into a set to quickly find out which of them occur at all.
+struct RegexSearcher {
+    set: RegexSet,
+    regexes: Vec<Regex>,
+    /// Index within the words map for each of the regular expressions.
+    ids: Vec<usize>,
+}
+
+impl RegexSearcher {
+    fn new(
+        words: &IndexMap<&Word<'_>, AtomicU64>,
+        ids: Vec<usize>,
+        folding: Folding,
+    ) -> Result<Self, regex::Error> {
+        // Without the original text, there is no way to match case sensitive if the whole article
+        // is case folded, so expressions become case insensitive as well.
+        let patterns: Vec<_> = ids
+            .iter()
+            .map(|&i| {
+                let w = words.get_index(i).unwrap().0;
+                let case = w.flags.ignore_case || folding.is_case_folded();
+                (w.regex().unwrap(), case)
+            })
+            .collect();
+
+        let set = RegexSet::new(patterns.iter().map(|(re, case)| {
+            if *case {
+                Cow::Owned(format!("(?i:{re})"))
+            } else {
+                Cow::Borrowed(re.as_ref())
+            }
+        }))?;
+        let regexes = patterns
+            .iter()
+            .map(|(re, case)| RegexBuilder::new(re).case_insensitive(*case).build())
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { set, regexes, ids })
+    }
+
+    fn find(&self, text: &Folded<'_>, mut f: impl FnMut(usize, &Folded<'_>, usize, usize)) {
+        if self.ids.is_empty() {
+            return;
+        }
+
+        for i in &self.set.matches(text.text()) {
+            for mat in self.regexes[i].find_iter(text.text()) {
+                f(self.ids[i], text, mat.start(), mat.end());
+            }
+        }
     }
 }
@@ -0,0 +1,149 @@
+//! Saving the counts of long scans from time to time, so they can be resumed after a crash.
+//!
+//! Chunks of the article are counted in parallel and finish in any order, so the counts of a chunk
+//! are held back until all chunks before it are done as well. A checkpoint therefore always covers
+//! a prefix of the article that was counted completely. The automata start over at every chunk,
+//! so the byte offset is all the state a scan needs to continue. It only reads a little of the
+//! text before the offset again, so the matches right after it are found the same way.
+//!
+//! Checkpoints are written as partial-count files, so they can be merged like any other part.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::partial::Partial;
+
+/// Writes checkpoints of the counts of all chunks that are done, in order.
+#[derive(Debug)]
+pub struct Checkpoint {
+    path: PathBuf,
+    interval: Duration,
+    /// Position within the article where reading starts.
+    base: u64,
+    state: Mutex<State>,
+    /// The end of the prefix covered by the last checkpoint, held while writing one.
+    written: Mutex<u64>,
+}
+
+#[derive(Debug)]
+struct State {
+    /// The counts of the prefix of the article that is done, starting at its first range.
+    done: Partial,
+    /// Counts of chunks that are done, but follow chunks that aren't, by their offset.
+    pending: BTreeMap<u64, (u64, Vec<u64>)>,
+    /// When the last checkpoint was taken.
+    taken: Instant,
+}
+
+impl Checkpoint {
+    /// Create checkpoints of the counts, starting with those in `done`, which must cover exactly
+    /// one range of the article. Reading starts at `base`, and further chunks must follow right
+    /// after that range.
+    pub fn new(path: impl Into<PathBuf>, interval: Duration, done: Partial, base: u64) -> Self {
+        let end = done.ranges[0].1;
+        Self {
+            path: path.into(),
+            interval,
+            base,
+            state: Mutex::new(State { done, pending: BTreeMap::new(), taken: Instant::now() }),
+            written: Mutex::new(end),
+        }
+    }
+
+    /// Add the matches of a chunk, given by its byte position from where reading started and its
+    /// length, as pairs of position and word ID. Writes a checkpoint if the last one is old enough,
+    /// after letting go of the counts, so other chunks can be added meanwhile. Failing to write one
+    /// only warns, as the scan itself can go on.
+    pub fn add(&self, offset: u64, len: u64, matched: &[(usize, usize)]) {
+        let mut state = self.state.lock().unwrap();
+        let mut counts = vec![0; state.done.counts.len()];
+        for &(_, id) in matched {
+            counts[id] += 1;
+        }
+        state.pending.insert(self.base + offset, (len, counts));
+
+        // Move every chunk that follows on the done prefix into it.
+        loop {
+            let end = state.done.ranges[0].1;
+            let Some((len, counts)) = state.pending.remove(&end) else {
+                break;
+            };
+            for (total, count) in state.done.counts.iter_mut().zip(counts) {
+                *total += count;
+            }
+            state.done.ranges[0].1 = end + len;
+        }
+
+        if state.taken.elapsed() < self.interval {
+            return;
+        }
+        state.taken = Instant::now();
+        let done = state.done.clone();
+        drop(state);
+
+        if let Err(e) = self.write(&done) {
+            eprintln!("warning: failed to write checkpoint {}: {e}", self.path.display());
+        }
+    }
+
+    /// Write the final checkpoint, covering everything that was counted.
+    pub fn finish(&self) -> io::Result<()> {
+        let done = self.state.lock().unwrap().done.clone();
+        self.write(&done)
+    }
+
+    /// Write the checkpoint to a temporary file first, so a crash while writing doesn't destroy the
+    /// previous one. Checkpoints taken by other threads in the meantime may cover more already, in
+    /// which case this one is left out.
+    fn write(&self, partial: &Partial) -> io::Result<()> {
+        let mut written = self.written.lock().unwrap();
+        let end = partial.ranges[0].1;
+        if end < *written {
+            return Ok(());
+        }
+
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+
+        let mut out = BufWriter::new(File::create(&tmp)?);
+        partial.write(&mut out)?;
+        out.flush()?;
+        fs::rename(tmp, &self.path)?;
+        *written = end;
+        Ok(())
+    }
+}
+
+/// Error returned when a scan can't be resumed from a checkpoint.
+#[derive(Debug)]
+pub enum ResumeError {
+    /// Checkpoints only ever cover a single range.
+    Ranges,
+    /// The range to count doesn't start where the checkpoint does.
+    Start(u64, u64),
+    /// Only the counts are saved, so options that report more can't be resumed.
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for ResumeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ranges => f.write_str("checkpoints must cover a single range of the article"),
+            Self::Start(checkpoint, range) => write!(
+                f,
+                "the checkpoint starts at byte {checkpoint}, but the range to count at {range}"
+            ),
+            Self::Unsupported(option) => {
+                write!(f, "checkpoints only cover the counts and can't be used with {option}")
+            }
+        }
+    }
+}
+
+impl Error for ResumeError {}
@@ -0,0 +1,306 @@
+//! Splitting the article into chunks of about the same size for the worker threads.
+//!
+//! Every chunk owns a part of the article and also holds some of the text around it, so matches
+//! that cross into a neighbouring chunk are still seen completely. This overlap is long enough for
+//! any match of a literal word, and each match is only counted by the chunk owning its start, or
+//! its end for fuzzy matches. Chunks end after a line break where possible. Regular expressions
+//! have no maximum length and units must see whole lines, so for these, chunks only ever end after
+//! a line break, and only matches of regular expressions that span lines may still be split.
+
+use std::io::{self, Read};
+use std::mem;
+use std::sync::mpsc;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+use crate::threads;
+
+/// Default size of the chunks.
+pub const CHUNK_SIZE: u64 = 4 << 20;
+
+/// A part of the article to scan.
+#[derive(Debug)]
+pub struct Chunk {
+    /// Position of the text within the article.
+    pub offset: u64,
+    pub text: String,
+    /// Bytes at the start that the previous chunk owns.
+    skip: usize,
+    /// Bytes at the end that the next chunk owns.
+    hold: usize,
+}
+
+impl Chunk {
+    /// Whether a match starting at this position of the text is counted by this chunk.
+    pub const fn owns(&self, start: usize) -> bool {
+        start >= self.skip && start < self.text.len() - self.hold
+    }
+
+    /// Whether a match ending at this position of the text is counted by this chunk.
+    pub const fn owns_end(&self, end: usize) -> bool {
+        end > self.skip && end <= self.text.len() - self.hold
+    }
+
+    /// The part of the text that this chunk owns, with its position within the article.
+    pub fn own(&self) -> (u64, &str) {
+        (self.offset + self.skip as u64, &self.text[self.skip..self.text.len() - self.hold])
+    }
+
+    /// Convert the position of an owned match within the text to one within [`Chunk::own`].
+    pub const fn own_position(&self, pos: usize) -> usize {
+        pos - self.skip
+    }
+}
+
+/// A ring of preallocated buffers to read chunks into. Once all of them are in use, the I/O thread
+/// waits for one to be recycled, so it doesn't read further ahead than the scanners keep up with.
+#[derive(Debug)]
+struct Pool {
+    ring: Mutex<Ring>,
+    recycled: Condvar,
+}
+
+#[derive(Debug)]
+struct Ring {
+    free: Vec<Vec<u8>>,
+    /// Set when scanning stopped early, so nobody waits for buffers anymore.
+    closed: bool,
+}
+
+impl Pool {
+    fn new(buffers: usize, capacity: usize) -> Self {
+        let free = (0..buffers).map(|_| Vec::with_capacity(capacity)).collect();
+        Self { ring: Mutex::new(Ring { free, closed: false }), recycled: Condvar::new() }
+    }
+
+    /// Take a free buffer, waiting for one to be recycled if all are in use. Returns `None` once
+    /// the pool is closed.
+    fn take(&self) -> Option<Vec<u8>> {
+        let mut ring = self.ring.lock().unwrap();
+        loop {
+            if ring.closed {
+                return None;
+            }
+            if let Some(buf) = ring.free.pop() {
+                return Some(buf);
+            }
+            ring = self.recycled.wait(ring).unwrap();
+        }
+    }
+
+    /// Hand back the buffer of a chunk that was scanned, to read another chunk into it.
+    fn recycle(&self, chunk: Chunk) {
+        let mut buf = chunk.text.into_bytes();
+        buf.clear();
+        self.ring.lock().unwrap().free.push(buf);
+        self.recycled.notify_one();
+    }
+
+    fn close(&self) {
+        self.ring.lock().unwrap().closed = true;
+        self.recycled.notify_all();
+    }
+}
+
+/// Closes the pool when dropped, also when unwinding from a panic.
+struct Close<'a>(&'a Pool);
+
+impl Drop for Close<'_> {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+/// The chunks of an article.
+#[derive(Debug)]
+pub struct Chunks<R> {
+    reader: R,
+    size: usize,
+    overlap: usize,
+    /// Number of buffers in the ring.
+    buffers: usize,
+    /// Position of the next chunk within the article.
+    offset: u64,
+    /// Bytes that were read for the next chunk already.
+    next: Vec<u8>,
+    /// How many of these the previous chunk covered.
+    skip: usize,
+    /// Never cut a line, but read on until the next line break instead.
+    whole_lines: bool,
+    done: bool,
+}
+
+impl<R: Read + Send> Chunks<R> {
+    /// Split the article into chunks owning about `size` bytes each, holding `overlap` bytes of
+    /// the neighbouring chunks on both sides. By default, there are two buffers per thread, so the
+    /// next chunk is ready whenever a thread is done with one.
+    pub fn new(reader: R, size: usize, overlap: usize) -> Self {
+        Self {
+            reader,
+            size: size.max(2 * overlap).max(64),
+            overlap,
+            buffers: 2 * threads::count(),
+            offset: 0,
+            next: vec![],
+            skip: 0,
+            whole_lines: false,
+            done: false,
+        }
+    }
+
+    /// Bytes each chunk holds at most: its own size, plus what the previous chunk held on to, which
+    /// is at most half a chunk, plus the overlap and a few bytes for cutting between characters.
+    /// With whole lines, the previous chunk may hold on to a whole chunk, and chunks with lines
+    /// longer than that grow beyond this size.
+    pub const fn buffer_size(&self) -> usize {
+        let held = if self.whole_lines { self.size } else { self.size / 2 };
+        self.size + held + self.overlap + 16
+    }
+
+    /// Number of buffers in the ring, which is the most chunks that exist at the same time.
+    pub const fn buffers(&self) -> usize {
+        self.buffers
+    }
+
+    /// Leave the first `bytes` of the article to a chunk before the first one, so they are only
+    /// read for the text before the matches after them. At most the overlap can be left out.
+    pub fn lead_in(mut self, bytes: usize) -> Self {
+        self.skip = bytes.min(self.overlap);
+        self
+    }
+
+    /// End every chunk after a line break, if `whole_lines` is set. Lines longer than a chunk make
+    /// it grow until the line is complete.
+    pub const fn whole_lines(mut self, whole_lines: bool) -> Self {
+        self.whole_lines = whole_lines;
+        self
+    }
+
+    /// Use at most `buffers` buffers, but at least one.
+    pub fn limit(mut self, buffers: usize) -> Self {
+        self.buffers = self.buffers.min(buffers).max(1);
+        self
+    }
+
+    /// Read the chunks on a dedicated I/O thread and call `f` for each of them on the threads that
+    /// scan them. Chunks are handed over through a channel bounded by the size of the ring, and
+    /// each buffer is recycled once `f` is done with its chunk. Stops at the first error, of
+    /// reading or of `f`, or as soon as `f` returns `false`, skipping the chunks left to scan.
+    pub fn read_ahead(mut self, f: impl Fn(&Chunk) -> io::Result<bool> + Sync) -> io::Result<()> {
+        let pool = Pool::new(self.buffers, self.buffer_size());
+        let (sender, receiver) = mpsc::sync_channel(self.buffers);
+
+        thread::scope(|s| {
+            let pool = &pool;
+            let reader = s.spawn(move || {
+                while let Some(chunk) = self.read(pool)? {
+                    // The scanners only hang up if they stopped early or panicked.
+                    if sender.send(chunk).is_err() {
+                        break;
+                    }
+                }
+                Ok(())
+            });
+
+            let scanned = threads::try_for_each(receiver.into_iter(), |chunk| {
+                // Unless scanning goes on, close the pool, so the I/O thread stops instead of
+                // waiting for buffers of chunks that are dropped without being scanned.
+                let close = Close(pool);
+                let go_on = f(&chunk);
+                pool.recycle(chunk);
+                match go_on {
+                    Ok(true) => {
+                        mem::forget(close);
+                        Ok(())
+                    }
+                    Ok(false) => Err(None),
+                    Err(e) => Err(Some(e)),
+                }
+            });
+
+            let read = reader.join().unwrap();
+            match scanned {
+                Err(Some(e)) => Err(e),
+                _ => read,
+            }
+        })
+    }
+
+    fn read(&mut self, pool: &Pool) -> io::Result<Option<Chunk>> {
+        if self.done {
+            return Ok(None);
+        }
+        let Some(mut buf) = pool.take() else {
+            return Ok(None);
+        };
+
+        buf.extend_from_slice(&self.next);
+        self.next.clear();
+        let mut skip = mem::take(&mut self.skip);
+        let mut read = (&mut self.reader).take(self.size as u64).read_to_end(&mut buf)?;
+
+        // Reading may start within a character of the lead-in, whose rest is left out.
+        if self.offset == 0 {
+            let partial = buf.iter().take(skip).take_while(|&&b| b & 0xc0 == 0x80).count();
+            buf.drain(..partial);
+            skip -= partial;
+            self.offset = partial as u64;
+        }
+
+        loop {
+            // The end of the article.
+            if read < self.size {
+                self.done = true;
+                let end = buf.len();
+                return if end > skip { self.chunk(buf, end, skip, 0).map(Some) } else { Ok(None) };
+            }
+
+            // Leave out the last character, which may be incomplete, and own everything up to the
+            // last line break that still leaves enough text to hold for the overlap. Lines that
+            // are too long are cut between two characters, and so are chunks whose last line break
+            // would leave more than half a chunk to hold, as the next chunk carries all of it into
+            // its buffer. Unless lines must stay whole, in which case the chunk grows instead.
+            let end = floor_char_boundary(&buf, buf.len() - 1);
+            let limit = floor_char_boundary(&buf, end - self.overlap);
+            let own_end = match buf[skip..limit].iter().rposition(|&b| b == b'\n') {
+                Some(i) if self.whole_lines || end - (skip + i + 1) <= self.size / 2 => {
+                    skip + i + 1
+                }
+                None if self.whole_lines => {
+                    read = (&mut self.reader).take(self.size as u64).read_to_end(&mut buf)?;
+                    continue;
+                }
+                _ => limit,
+            };
+            return self.cut(buf, end, skip, own_end).map(Some);
+        }
+    }
+
+    /// Cut the chunk from `buf` at `own_end`, keeping the rest for the next chunk.
+    fn cut(&mut self, buf: Vec<u8>, end: usize, skip: usize, own_end: usize) -> io::Result<Chunk> {
+        let next = floor_char_boundary(&buf, own_end.saturating_sub(self.overlap));
+        self.skip = own_end - next;
+
+        self.next.extend_from_slice(&buf[next..]);
+        let chunk = self.chunk(buf, end, skip, end - own_end);
+        self.offset += next as u64;
+        chunk
+    }
+
+    fn chunk(&self, mut buf: Vec<u8>, end: usize, skip: usize, hold: usize) -> io::Result<Chunk> {
+        buf.truncate(end);
+        let text = String::from_utf8(buf).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidData, "stream did not contain valid UTF-8")
+        })?;
+
+        Ok(Chunk { offset: self.offset, text, skip, hold })
+    }
+}
+
+/// The start of the character at `pos`, which may be a continuation byte of UTF-8.
+fn floor_char_boundary(buf: &[u8], mut pos: usize) -> usize {
+    while pos > 0 && buf[pos] & 0xc0 == 0x80 {
+        pos -= 1;
+    }
+    pos
+}
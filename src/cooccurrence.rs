@@ -0,0 +1,251 @@
+//! Counting how often pairs of words are found near each other.
+//!
+//! Two matches co-occur if their starts are at most a window apart, measured in bytes, tokens or
+//! lines. Each chunk counts the pairs within itself and keeps the matches close enough to its start
+//! or end to pair with matches of a neighbouring chunk. Once all chunks are done, these edges are
+//! walked in order to count the pairs that cross from one chunk into the next.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use serde::Serialize;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::chunks::Chunk;
+
+/// How the distance between two matches is measured.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Unit {
+    Bytes,
+    Tokens,
+    Lines,
+}
+
+/// The maximum distance between two matches to count them as a pair.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Window {
+    pub size: u64,
+    pub unit: Unit,
+}
+
+impl FromStr for Window {
+    type Err = InvalidWindow;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let unit = match &s[split..] {
+            "" | "b" => Unit::Bytes,
+            "t" => Unit::Tokens,
+            "l" => Unit::Lines,
+            _ => return Err(InvalidWindow(s.to_owned())),
+        };
+        let size = s[..split].parse().map_err(|_| InvalidWindow(s.to_owned()))?;
+
+        Ok(Self { size, unit })
+    }
+}
+
+/// Error returned when parsing an invalid window.
+#[derive(Debug)]
+pub struct InvalidWindow(String);
+
+impl fmt::Display for InvalidWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid window `{}`, expected a number followed by `b` (bytes), `t` (tokens) or `l` \
+             (lines)",
+            self.0
+        )
+    }
+}
+
+impl Error for InvalidWindow {}
+
+/// Output format of the matrix.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Format {
+    #[default]
+    Csv,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = UnknownFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            _ => Err(UnknownFormat(s.to_owned())),
+        }
+    }
+}
+
+/// Error returned when parsing an unsupported matrix format.
+#[derive(Debug)]
+pub struct UnknownFormat(String);
+
+impl fmt::Display for UnknownFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown co-occurrence format `{}`, expected one of `csv` or `json`", self.0)
+    }
+}
+
+impl Error for UnknownFormat {}
+
+/// Sparse matrix of the pair counts, keyed by the indices of both words in ascending order.
+pub type Pairs = HashMap<(usize, usize), u64>;
+
+/// Matches of a chunk that may pair with matches of its neighbours, with their position in the
+/// chunk's unit.
+struct Edge {
+    /// Length of the chunk in the window's unit.
+    len: u64,
+    head: Vec<(u64, usize)>,
+    tail: Vec<(u64, usize)>,
+}
+
+/// Collects the pairs of all chunks.
+pub struct Matrix {
+    window: Window,
+    pairs: Mutex<Pairs>,
+    edges: Mutex<Vec<(u64, Edge)>>,
+}
+
+impl Matrix {
+    pub fn new(window: Window) -> Self {
+        Self { window, pairs: Mutex::default(), edges: Mutex::default() }
+    }
+
+    /// Add the matches of a chunk, given by their byte position within the part the chunk owns and
+    /// the index of their word.
+    pub fn add(&self, chunk: &Chunk, mut matches: Vec<(usize, usize)>) {
+        matches.sort_unstable();
+
+        let (len, matches) = self.measure(chunk, &matches);
+        let size = self.window.size;
+
+        let mut pairs = Pairs::new();
+        for (i, &(pos, a)) in matches.iter().enumerate() {
+            for &(_, b) in matches[i + 1..].iter().take_while(|&&(p, _)| p - pos <= size) {
+                add_pair(&mut pairs, a, b);
+            }
+        }
+
+        // A line cut where the chunk ends continues in the next one, so matches on it pair with
+        // matches up to a whole window into the next chunk.
+        let head = matches.iter().copied().take_while(|&(p, _)| p <= size).collect();
+        let tail = matches.iter().copied().filter(|&(p, _)| p + size >= len).collect();
+        self.edges.lock().unwrap().push((chunk.own().0, Edge { len, head, tail }));
+
+        let mut all = self.pairs.lock().unwrap();
+        for (pair, n) in pairs {
+            *all.entry(pair).or_default() += n;
+        }
+    }
+
+    /// Convert the byte positions of the sorted matches into the window's unit, returning the
+    /// length of the owned part of the chunk in that unit as well. Lines and tokens cut where the
+    /// chunk ends are only counted by the chunk owning their start.
+    fn measure(&self, chunk: &Chunk, matches: &[(usize, usize)]) -> (u64, Vec<(u64, usize)>) {
+        let (_, own) = chunk.own();
+        match self.window.unit {
+            Unit::Bytes => {
+                (own.len() as u64, matches.iter().map(|&(p, id)| (p as u64, id)).collect())
+            }
+            Unit::Tokens => {
+                // Tokenize the whole chunk, so tokens cut at the start of the owned part are seen
+                // completely and left to the previous chunk.
+                let ends: Vec<_> = chunk
+                    .text
+                    .unicode_word_indices()
+                    .filter(|&(i, _)| chunk.owns(i))
+                    .map(|(i, t)| chunk.own_position(i) + t.len())
+                    .collect();
+                let pos = |p: usize| ends.partition_point(|&end| end <= p) as u64;
+                (ends.len() as u64, matches.iter().map(|&(p, id)| (pos(p), id)).collect())
+            }
+            Unit::Lines => {
+                let mut line = 0;
+                let mut last = 0;
+                let matches = matches
+                    .iter()
+                    .map(|&(p, id)| {
+                        line += own[last..p].matches('\n').count();
+                        last = p;
+                        (line as u64, id)
+                    })
+                    .collect();
+                (own.matches('\n').count() as u64, matches)
+            }
+        }
+    }
+
+    /// Count the pairs that cross chunks and return all of them.
+    pub fn finish(self) -> Pairs {
+        let size = self.window.size;
+        let mut pairs = self.pairs.into_inner().unwrap();
+        let mut edges = self.edges.into_inner().unwrap();
+        edges.sort_unstable_by_key(|&(offset, _)| offset);
+
+        // Matches of previous chunks that are still within reach, with their global position.
+        let mut carry: Vec<(u64, usize)> = vec![];
+        let mut base = 0;
+
+        for (_, Edge { len, head, tail }) in edges {
+            for (pos, a) in head {
+                for &(_, b) in carry.iter().filter(|&&(p, _)| base + pos - p <= size) {
+                    add_pair(&mut pairs, a, b);
+                }
+            }
+
+            carry.extend(tail.into_iter().map(|(p, id)| (base + p, id)));
+            base += len;
+            carry.retain(|&(p, _)| p + size >= base);
+        }
+
+        pairs
+    }
+}
+
+fn add_pair(pairs: &mut Pairs, a: usize, b: usize) {
+    *pairs.entry((a.min(b), a.max(b))).or_default() += 1;
+}
+
+#[derive(Serialize)]
+struct Triple<'a> {
+    a: &'a str,
+    b: &'a str,
+    count: u64,
+}
+
+/// Write the pairs as triples of both word ids and their count, sorted by the words' order.
+pub fn write(pairs: &Pairs, ids: &[String], format: Format, out: impl io::Write) -> io::Result<()> {
+    let mut pairs: Vec<_> = pairs.iter().collect();
+    pairs.sort_unstable();
+
+    let triples =
+        pairs.into_iter().map(|(&(a, b), &count)| Triple { a: &ids[a], b: &ids[b], count });
+
+    match format {
+        Format::Csv => {
+            // Write the header explicitly, so it's there even without any pairs.
+            let mut out = csv::WriterBuilder::new().has_headers(false).from_writer(out);
+            out.write_record(["a", "b", "count"])?;
+            for triple in triples {
+                out.serialize(triple)?;
+            }
+            out.flush()
+        }
+        Format::Json => {
+            let mut out = out;
+            serde_json::to_writer_pretty(&mut out, &triples.collect::<Vec<_>>())?;
+            writeln!(out)
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! Unicode-aware case folding and normalization of patterns and article text.
+//!
+//! Both sides of the search have to be folded the same way, so `Straße` in the words file finds
+//! `STRASSE` in the article and precomposed accents match their decomposed forms. As folding can
+//! change the length of the text, the article is folded together with a mapping that allows
+//! translating positions in the folded text back into byte offsets of the original input.
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use caseless::Caseless;
+use unicode_normalization::char::{canonical_combining_class, compose};
+use unicode_normalization::UnicodeNormalization;
+
+/// Unicode normalization form applied before matching.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Normalization {
+    /// Canonical decomposition, followed by canonical composition.
+    Nfc,
+    /// Compatibility decomposition, followed by canonical composition.
+    Nfkc,
+}
+
+impl Normalization {
+    fn extend(self, out: &mut String, chars: impl Iterator<Item = char>) {
+        match self {
+            Self::Nfc => out.extend(chars.nfc()),
+            Self::Nfkc => out.extend(chars.nfkc()),
+        }
+    }
+}
+
+impl FromStr for Normalization {
+    type Err = UnknownNormalization;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nfc" => Ok(Self::Nfc),
+            "nfkc" => Ok(Self::Nfkc),
+            _ => Err(UnknownNormalization(s.to_owned())),
+        }
+    }
+}
+
+/// Error returned when parsing an unsupported normalization form.
+#[derive(Debug)]
+pub struct UnknownNormalization(String);
+
+impl fmt::Display for UnknownNormalization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown normalization form `{}`, expected `nfc` or `nfkc`", self.0)
+    }
+}
+
+impl Error for UnknownNormalization {}
+
+/// Settings that describe how patterns and article are folded before the search.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Folding {
+    case: bool,
+    normalization: Option<Normalization>,
+}
+
+impl Folding {
+    pub const fn new(case: bool, normalization: Option<Normalization>) -> Self {
+        Self { case, normalization }
+    }
+
+    /// The same folding, but with case folding enabled.
+    pub const fn with_case(self) -> Self {
+        Self { case: true, ..self }
+    }
+
+    /// Whether the case of patterns and article is folded.
+    pub const fn is_case_folded(self) -> bool {
+        self.case
+    }
+
+    /// Whether folding leaves any input untouched.
+    pub const fn is_identity(self) -> bool {
+        !self.case && self.normalization.is_none()
+    }
+
+    /// Fold a single pattern.
+    pub fn fold(self, s: &str) -> String {
+        let mut out = String::with_capacity(s.len());
+        self.fold_into(s, &mut out);
+        out
+    }
+
+    /// Fold a piece of the article and keep track of the offsets that changed along the way.
+    pub fn fold_mapped(self, s: &str) -> Folded<'_> {
+        // Normalization doesn't change pure ASCII text, so without case folding we can skip all
+        // the work for the common case of the challenge input.
+        if self.is_identity() || (!self.case && s.is_ascii()) {
+            return Folded { text: Cow::Borrowed(s), spans: vec![] };
+        }
+
+        let mut text = String::with_capacity(s.len());
+        let mut spans = vec![];
+
+        for (start, segment) in Segments::new(s) {
+            let folded_start = text.len();
+
+            match segment.as_bytes() {
+                [b] if b.is_ascii() => {
+                    text.push(if self.case { b.to_ascii_lowercase() } else { *b } as char);
+                }
+                _ => self.fold_into(segment, &mut text),
+            }
+
+            if text.len() - folded_start != segment.len() {
+                spans.push(Span {
+                    folded: (folded_start, text.len()),
+                    original: (start, start + segment.len()),
+                });
+            }
+        }
+
+        Folded { text: Cow::Owned(text), spans }
+    }
+
+    fn fold_into(self, s: &str, out: &mut String) {
+        match (self.case, self.normalization) {
+            (false, None) => out.push_str(s),
+            (true, None) => out.extend(s.chars().default_case_fold()),
+            (false, Some(n)) => n.extend(out, s.chars()),
+            (true, Some(n)) => {
+                // Case folding can produce unnormalized output, so normalize before and after.
+                let mut normalized = String::with_capacity(s.len());
+                n.extend(&mut normalized, s.chars());
+                n.extend(out, normalized.chars().default_case_fold());
+            }
+        }
+    }
+}
+
+/// Folded text together with the information to map positions back to the original input.
+pub struct Folded<'a> {
+    text: Cow<'a, str>,
+    /// Segments whose length changed by folding, ordered by position.
+    spans: Vec<Span>,
+}
+
+impl Folded<'_> {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Translate the start of a match in the folded text to an offset in the original input.
+    pub fn original_start(&self, pos: usize) -> usize {
+        let i = self.spans.partition_point(|s| s.folded.0 <= pos);
+        match i.checked_sub(1).map(|i| &self.spans[i]) {
+            None => pos,
+            Some(s) if pos < s.folded.1 => s.original.0,
+            Some(s) => pos - s.folded.1 + s.original.1,
+        }
+    }
+
+    /// Translate the end of a match in the folded text to an offset in the original input.
+    pub fn original_end(&self, pos: usize) -> usize {
+        let i = self.spans.partition_point(|s| s.folded.0 < pos);
+        match i.checked_sub(1).map(|i| &self.spans[i]) {
+            None => pos,
+            Some(s) if pos <= s.folded.1 => s.original.1,
+            Some(s) => pos - s.folded.1 + s.original.1,
+        }
+    }
+}
+
+/// A segment of the input that was folded into a text of different length, as byte ranges in the
+/// folded text and the original input.
+struct Span {
+    folded: (usize, usize),
+    original: (usize, usize),
+}
+
+/// Iterator over the smallest pieces of a text that can be folded independently. Each piece
+/// starts with a character that doesn't combine with its predecessor, followed by all the
+/// combining marks that belong to it.
+struct Segments<'a> {
+    s: &'a str,
+    pos: usize,
+}
+
+impl<'a> Segments<'a> {
+    const fn new(s: &'a str) -> Self {
+        Self { s, pos: 0 }
+    }
+}
+
+impl<'a> Iterator for Segments<'a> {
+    type Item = (usize, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.pos;
+        let mut chars = self.s[start..].chars();
+        let mut last = chars.next()?;
+        let mut end = start + last.len_utf8();
+
+        if !last.is_ascii() || matches!(self.s.as_bytes().get(end), Some(b) if !b.is_ascii()) {
+            for c in chars {
+                if canonical_combining_class(c) == 0 && compose(last, c).is_none() {
+                    break;
+                }
+                last = c;
+                end += c.len_utf8();
+            }
+        }
+
+        self.pos = end;
+        Some((start, &self.s[start..end]))
+    }
+}
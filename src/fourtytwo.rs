@@ -5,10 +5,12 @@ use std::sync::atomic::{AtomicU64, Ordering};
 
 use indexmap::map::IndexMap;
 
+use crate::words::Word;
+
 /// Answer to the Ultimate Question of Life, the Universe, and Everything.
 const THE_ANSWER_TO_EVERYTHING: u64 = 42;
 
-pub fn process<R>(words: &IndexMap<&str, AtomicU64>, article: BufReader<R>)
+pub fn process<R>(words: &IndexMap<&Word<'_>, AtomicU64>, article: BufReader<R>)
 where
     R: Read + Send,
 {
@@ -0,0 +1,348 @@
+//! Approximate matching of words within a maximum amount of edits (insertions, deletions and
+//! substitutions, also known as the Levenshtein distance).
+//!
+//! Searching the whole article with an approximate matcher would be far too slow. Instead, each
+//! word is split into `K + 1` pieces. By the pigeonhole principle, any occurrence with at most `K`
+//! edits contains at least one of these pieces unchanged. The pieces of all words are found with
+//! the exact automaton and only the regions around them are verified with [Myers' bit-parallel
+//! algorithm][myers].
+//!
+//! As an approximate match can end at several neighbouring positions, each run of consecutive end
+//! positions counts as a single occurrence. Runs that contain an exact match are left to the exact
+//! search and only the remaining ones are counted as fuzzy matches.
+//!
+//! [myers]: https://doi.org/10.1145/316542.316550
+
+use crate::aho_corasick::AhoCorasick;
+use crate::memory::{self, Budget, OutOfMemory};
+
+/// Finds approximate occurrences of a set of words.
+pub struct FuzzySearcher {
+    ac: AhoCorasick,
+    /// For each piece of the automaton, the index of its word in `words` and its offset within it.
+    pieces: Vec<(usize, usize)>,
+    /// Index within the words map and the (folded) text of each word.
+    words: Vec<(usize, Vec<u8>)>,
+    max_edits: usize,
+}
+
+impl FuzzySearcher {
+    /// Create a searcher for the given words. Words that aren't longer than `max_edits` are left
+    /// out, as they would match anywhere.
+    pub fn new(
+        words: impl IntoIterator<Item = (usize, Vec<u8>)>,
+        max_edits: usize,
+        budget: Option<&Budget>,
+    ) -> Result<Self, OutOfMemory> {
+        let words: Vec<_> = words.into_iter().filter(|(_, w)| w.len() > max_edits).collect();
+        let mut patterns = vec![];
+        let mut pieces = vec![];
+
+        for (i, (_, word)) in words.iter().enumerate() {
+            let n = max_edits + 1;
+            for p in 0..n {
+                let (start, end) = (p * word.len() / n, (p + 1) * word.len() / n);
+                patterns.push(&word[start..end]);
+                pieces.push((i, start));
+            }
+        }
+
+        Ok(Self { ac: memory::automaton(&patterns, budget)?, pieces, words, max_edits })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Find all fuzzy occurrences in the text and report the index of their word, along with the
+    /// end of the first position the occurrence matches at.
+    pub fn find(&self, text: &[u8], mut f: impl FnMut(usize, usize)) {
+        if self.is_empty() {
+            return;
+        }
+
+        let k = self.max_edits;
+        let mut windows: Vec<_> = self
+            .ac
+            .find_overlapping_iter(text)
+            .map(|mat| {
+                let (i, offset) = self.pieces[mat.pattern()];
+                let start = mat.start().saturating_sub(offset + k);
+                let end = (mat.start().saturating_sub(offset) + self.words[i].1.len() + k)
+                    .min(text.len());
+                (i, start, end)
+            })
+            .collect();
+
+        windows.sort_unstable();
+        windows.dedup();
+
+        let mut windows = windows.into_iter().peekable();
+        while let Some((i, start, mut end)) = windows.next() {
+            // Merge overlapping windows of the same word, so runs aren't split or counted twice.
+            while let Some(&(_, _, next_end)) =
+                windows.peek().filter(|&&(j, next_start, _)| j == i && next_start <= end)
+            {
+                end = end.max(next_end);
+                windows.next();
+            }
+
+            fuzzy_runs(&self.words[i].1, &text[start..end], k, |run| {
+                f(self.words[i].0, start + run);
+            });
+        }
+    }
+}
+
+/// Find the runs of end positions in `text` where `pattern` matches with at most `k` edits, but
+/// not exactly, and report the first end position of each.
+fn fuzzy_runs(pattern: &[u8], text: &[u8], k: usize, mut f: impl FnMut(usize)) {
+    let mut run = None;
+    let mut exact = false;
+    let mut end = 0;
+
+    let mut end_run = |run: &mut Option<usize>, exact: &mut bool| {
+        if let Some(first) = run.take().filter(|_| !*exact) {
+            f(first);
+        }
+        *exact = false;
+    };
+
+    let mut visit = |distance: usize| {
+        end += 1;
+        if distance <= k {
+            run.get_or_insert(end);
+            exact |= distance == 0;
+        } else {
+            end_run(&mut run, &mut exact);
+        }
+    };
+
+    if pattern.len() <= 64 {
+        myers(pattern, text, &mut visit);
+    } else {
+        sellers(pattern, text, &mut visit);
+    }
+
+    end_run(&mut run, &mut exact);
+}
+
+/// Myers' bit-parallel algorithm, reporting the edit distance of the best match of `pattern`
+/// ending at each position of `text`. The pattern must not be longer than 64 bytes.
+fn myers(pattern: &[u8], text: &[u8], mut f: impl FnMut(usize)) {
+    let m = pattern.len();
+    let mut peq = [0_u64; 256];
+    for (i, &b) in pattern.iter().enumerate() {
+        peq[b as usize] |= 1 << i;
+    }
+
+    let mask = if m == 64 { u64::MAX } else { (1 << m) - 1 };
+    let high = 1 << (m - 1);
+    let mut pv = mask;
+    let mut mv = 0_u64;
+    let mut score = m;
+
+    for &b in text {
+        let eq = peq[b as usize];
+        let xv = eq | mv;
+        let xh = (((eq & pv).wrapping_add(pv)) ^ pv) | eq;
+        let mut ph = mv | !(xh | pv);
+        let mut mh = pv & xh;
+
+        if ph & high != 0 {
+            score += 1;
+        } else if mh & high != 0 {
+            score -= 1;
+        }
+
+        // Not shifting in a one allows matches to start anywhere in the text.
+        ph = (ph << 1) & mask;
+        mh = (mh << 1) & mask;
+        pv = (mh | !(xv | ph)) & mask;
+        mv = ph & xv;
+
+        f(score);
+    }
+}
+
+/// The classic dynamic programming approach by Sellers, for patterns too long for [`myers`].
+fn sellers(pattern: &[u8], text: &[u8], mut f: impl FnMut(usize)) {
+    let mut column: Vec<_> = (0..=pattern.len()).collect();
+
+    for &b in text {
+        let mut diagonal = column[0];
+        for (i, &p) in pattern.iter().enumerate() {
+            let next = (diagonal + usize::from(p != b)).min(column[i + 1] + 1).min(column[i] + 1);
+            diagonal = column[i + 1];
+            column[i + 1] = next;
+        }
+
+        f(column[pattern.len()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use indexmap::IndexMap;
+
+    use super::*;
+    use crate::ahocorasick::{self, Settings};
+    use crate::chunks::CHUNK_SIZE;
+    use crate::words::{self, Format};
+
+    /// A simple pseudo-random generator, to get the same cases on every run.
+    struct Random(u64);
+
+    impl Random {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 = self
+                .0
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            usize::try_from(self.0 >> 33).unwrap() % n
+        }
+
+        /// Random text over a small alphabet, so words and text resemble each other.
+        fn text(&mut self, len: usize) -> Vec<u8> {
+            (0..len).map(|_| b"abc"[self.below(3)]).collect()
+        }
+
+        /// The word with up to `k` random edits.
+        fn edit(&mut self, word: &[u8], k: usize) -> Vec<u8> {
+            let mut word = word.to_vec();
+            for _ in 0..self.below(k + 1) {
+                let i = self.below(word.len() + 1);
+                match self.below(3) {
+                    0 => word.insert(i, b'x'),
+                    1 if i < word.len() => word[i] = b'y',
+                    _ if i < word.len() => drop(word.remove(i)),
+                    _ => {}
+                }
+            }
+            word
+        }
+
+        /// Random text with some edited copies of the word in it.
+        fn article(&mut self, word: &[u8], k: usize) -> Vec<u8> {
+            let mut text = vec![];
+            for _ in 0..4 {
+                let len = self.below(20);
+                text.extend(self.text(len));
+                let copy = self.edit(word, k);
+                text.extend(copy);
+            }
+            text
+        }
+    }
+
+    /// The least edit distance of the pattern to any substring ending at each position, trying
+    /// every start of the substring.
+    fn naive(pattern: &[u8], text: &[u8]) -> Vec<usize> {
+        let mut best = vec![pattern.len(); text.len()];
+        for start in 0..text.len() {
+            // The distances of all prefixes of the pattern to the text from `start` to `end`.
+            let mut column: Vec<_> = (0..=pattern.len()).collect();
+            for (end, &b) in text.iter().enumerate().skip(start) {
+                let mut diagonal = column[0];
+                column[0] += 1;
+                for (i, &p) in pattern.iter().enumerate() {
+                    let next =
+                        (diagonal + usize::from(p != b)).min(column[i] + 1).min(column[i + 1] + 1);
+                    diagonal = column[i + 1];
+                    column[i + 1] = next;
+                }
+                best[end] = best[end].min(column[pattern.len()]);
+            }
+        }
+        best
+    }
+
+    /// The first end of each run of approximate but not exact matches.
+    fn naive_runs(pattern: &[u8], text: &[u8], k: usize) -> Vec<usize> {
+        let distances = naive(pattern, text);
+        let mut runs = vec![];
+        let mut i = 0;
+        while i < distances.len() {
+            let len = distances[i..].iter().take_while(|&&d| d <= k).count();
+            if len > 0 && !distances[i..i + len].contains(&0) {
+                runs.push(i + 1);
+            }
+            i += len.max(1);
+        }
+        runs
+    }
+
+    #[test]
+    fn same_distances_as_naive_search() {
+        let mut random = Random(1);
+        for len in [1, 2, 5, 17, 63, 64, 65, 90] {
+            for k in [1, 3] {
+                let pattern = random.text(len);
+                let text = random.article(&pattern, k);
+                let expected = naive(&pattern, &text);
+
+                if len <= 64 {
+                    let mut distances = vec![];
+                    myers(&pattern, &text, |d| distances.push(d));
+                    assert_eq!(distances, expected, "{len} {k}");
+                }
+                let mut distances = vec![];
+                sellers(&pattern, &text, |d| distances.push(d));
+                assert_eq!(distances, expected, "{len} {k}");
+            }
+        }
+    }
+
+    #[test]
+    fn same_runs_as_naive_search() {
+        let mut random = Random(2);
+        for len in [3, 8, 30, 64, 65, 80] {
+            for k in 1..3 {
+                let word = random.text(len);
+                let text = random.article(&word, k);
+                let searcher = FuzzySearcher::new([(7, word.clone())], k, None).unwrap();
+                let mut found = vec![];
+                searcher.find(&text, |id, end| found.push((id, end)));
+
+                let expected: Vec<_> =
+                    naive_runs(&word, &text, k).into_iter().map(|end| (7, end)).collect();
+                assert_eq!(found, expected, "{len} {k}");
+            }
+        }
+    }
+
+    /// Each run is counted by the chunk its first end lies in, so no chunk size changes counts,
+    /// wherever the chunks are cut.
+    #[test]
+    fn same_counts_for_any_chunk_size() {
+        let mut random = Random(3);
+        let short = random.text(6);
+        let long = random.text(70);
+        let mut article = vec![];
+        for _ in 0..20 {
+            article.extend(random.article(&short, 1));
+            article.extend(random.article(&long, 1));
+        }
+
+        let words = [short, long].map(|w| String::from_utf8(w).unwrap()).join("\n");
+        let words = words::parse(&words, Format::Plain).unwrap();
+        let words: IndexMap<_, _> =
+            words.words().iter().map(|w| (w, AtomicU64::default())).collect();
+        let count = |chunk_size| {
+            let settings = Settings { max_edits: Some(1), chunk_size, ..Settings::default() };
+            let report =
+                ahocorasick::process(&words, BufReader::new(article.as_slice()), settings).unwrap();
+            let exact: Vec<_> = words.values().map(|n| n.swap(0, Ordering::SeqCst)).collect();
+            (exact, report.fuzzy)
+        };
+
+        let (exact, fuzzy) = count(CHUNK_SIZE);
+        assert!(fuzzy.iter().all(|&n| n > 0));
+        for chunk_size in 600..640 {
+            assert_eq!(count(chunk_size), (exact.clone(), fuzzy.clone()), "{chunk_size}");
+        }
+    }
+}
@@ -0,0 +1,333 @@
+//! Streaming the visible text of HTML.
+//!
+//! Tags and comments are removed, as is the content of scripts and styles. Block elements like
+//! paragraphs become line breaks, so words of neighbouring blocks don't run into each other, while
+//! inline elements are removed without a trace. Character references are decoded.
+
+use super::markup::Syntax;
+use super::offsets::Output;
+
+/// Elements whose content isn't visible.
+const HIDDEN: &[&[u8]] = &[b"script", b"style", b"template"];
+
+/// Elements that start on a new line.
+const BLOCKS: &[&[u8]] = &[
+    b"address",
+    b"article",
+    b"aside",
+    b"blockquote",
+    b"br",
+    b"dd",
+    b"div",
+    b"dl",
+    b"dt",
+    b"figcaption",
+    b"figure",
+    b"footer",
+    b"form",
+    b"h1",
+    b"h2",
+    b"h3",
+    b"h4",
+    b"h5",
+    b"h6",
+    b"header",
+    b"hr",
+    b"li",
+    b"main",
+    b"nav",
+    b"ol",
+    b"option",
+    b"p",
+    b"pre",
+    b"section",
+    b"table",
+    b"td",
+    b"th",
+    b"title",
+    b"tr",
+    b"ul",
+];
+
+/// Named character references that are decoded. This covers the common ones, not all of HTML.
+const NAMED: &[(&str, char)] = &[
+    ("amp", '&'),
+    ("lt", '<'),
+    ("gt", '>'),
+    ("quot", '"'),
+    ("apos", '\''),
+    ("nbsp", '\u{a0}'),
+    ("shy", '\u{ad}'),
+    ("copy", '©'),
+    ("reg", '®'),
+    ("trade", '™'),
+    ("hellip", '…'),
+    ("ndash", '–'),
+    ("mdash", '—'),
+    ("lsquo", '‘'),
+    ("rsquo", '’'),
+    ("sbquo", '‚'),
+    ("ldquo", '“'),
+    ("rdquo", '”'),
+    ("bdquo", '„'),
+    ("laquo", '«'),
+    ("raquo", '»'),
+    ("middot", '·'),
+    ("bull", '•'),
+    ("deg", '°'),
+    ("plusmn", '±'),
+    ("times", '×'),
+    ("divide", '÷'),
+    ("micro", 'µ'),
+    ("para", '¶'),
+    ("sect", '§'),
+    ("cent", '¢'),
+    ("pound", '£'),
+    ("euro", '€'),
+    ("yen", '¥'),
+    ("iexcl", '¡'),
+    ("iquest", '¿'),
+    ("frac12", '½'),
+    ("frac14", '¼'),
+    ("frac34", '¾'),
+    ("Agrave", 'À'),
+    ("Aacute", 'Á'),
+    ("Acirc", 'Â'),
+    ("Atilde", 'Ã'),
+    ("Auml", 'Ä'),
+    ("Aring", 'Å'),
+    ("AElig", 'Æ'),
+    ("Ccedil", 'Ç'),
+    ("Egrave", 'È'),
+    ("Eacute", 'É'),
+    ("Ecirc", 'Ê'),
+    ("Euml", 'Ë'),
+    ("Igrave", 'Ì'),
+    ("Iacute", 'Í'),
+    ("Icirc", 'Î'),
+    ("Iuml", 'Ï'),
+    ("Ntilde", 'Ñ'),
+    ("Ograve", 'Ò'),
+    ("Oacute", 'Ó'),
+    ("Ocirc", 'Ô'),
+    ("Otilde", 'Õ'),
+    ("Ouml", 'Ö'),
+    ("Oslash", 'Ø'),
+    ("Ugrave", 'Ù'),
+    ("Uacute", 'Ú'),
+    ("Ucirc", 'Û'),
+    ("Uuml", 'Ü'),
+    ("Yacute", 'Ý'),
+    ("szlig", 'ß'),
+    ("agrave", 'à'),
+    ("aacute", 'á'),
+    ("acirc", 'â'),
+    ("atilde", 'ã'),
+    ("auml", 'ä'),
+    ("aring", 'å'),
+    ("aelig", 'æ'),
+    ("ccedil", 'ç'),
+    ("egrave", 'è'),
+    ("eacute", 'é'),
+    ("ecirc", 'ê'),
+    ("euml", 'ë'),
+    ("igrave", 'ì'),
+    ("iacute", 'í'),
+    ("icirc", 'î'),
+    ("iuml", 'ï'),
+    ("ntilde", 'ñ'),
+    ("ograve", 'ò'),
+    ("oacute", 'ó'),
+    ("ocirc", 'ô'),
+    ("otilde", 'õ'),
+    ("ouml", 'ö'),
+    ("oslash", 'ø'),
+    ("ugrave", 'ù'),
+    ("uacute", 'ú'),
+    ("ucirc", 'û'),
+    ("uuml", 'ü'),
+    ("yacute", 'ý'),
+    ("yuml", 'ÿ'),
+];
+
+/// Longest name of a character reference that is still considered.
+const MAX_REFERENCE: usize = 32;
+
+#[derive(Debug, Default)]
+enum State {
+    #[default]
+    Text,
+    /// After a `<` that may start a tag.
+    Open(u64),
+    Tag {
+        start: u64,
+        name: Vec<u8>,
+        closing: bool,
+        /// Whether the name is complete and attributes follow.
+        named: bool,
+        quote: Option<u8>,
+    },
+    Comment {
+        dashes: usize,
+    },
+    /// Within an element whose content is hidden, up to its closing tag.
+    Hidden {
+        name: Vec<u8>,
+        tail: Vec<u8>,
+    },
+    Reference {
+        start: u64,
+        name: Vec<u8>,
+    },
+}
+
+#[derive(Debug, Default)]
+pub struct Html {
+    state: State,
+}
+
+impl Html {
+    /// Whether the next byte is taken as text, unless it starts markup.
+    pub const fn is_text(&self) -> bool {
+        matches!(self.state, State::Text)
+    }
+
+    /// Convert the next byte of the source, found at the given position.
+    pub fn feed(&mut self, b: u8, pos: u64, out: &mut Output) {
+        match &mut self.state {
+            State::Text => match b {
+                b'<' => self.state = State::Open(pos),
+                b'&' => self.state = State::Reference { start: pos, name: vec![] },
+                _ => out.push(b, pos),
+            },
+            &mut State::Open(start) => {
+                if b.is_ascii_alphabetic() || matches!(b, b'/' | b'!' | b'?') {
+                    let name = if b == b'/' { vec![] } else { vec![b.to_ascii_lowercase()] };
+                    self.state =
+                        State::Tag { start, name, closing: b == b'/', named: false, quote: None };
+                } else {
+                    // Just a less-than sign.
+                    out.push(b'<', start);
+                    self.state = State::Text;
+                    self.feed(b, pos, out);
+                }
+            }
+            State::Tag { start, name, closing, named, quote } => {
+                if let Some(q) = *quote {
+                    if b == q {
+                        *quote = None;
+                    }
+                    return;
+                }
+
+                if !*named {
+                    if b.is_ascii_alphanumeric() || matches!(b, b'-' | b':' | b'!' | b'?') {
+                        name.push(b.to_ascii_lowercase());
+                        if name == b"!--" {
+                            self.state = State::Comment { dashes: 0 };
+                        }
+                        return;
+                    }
+                    *named = true;
+                }
+
+                match b {
+                    b'"' | b'\'' => *quote = Some(b),
+                    b'>' => {
+                        let (start, closing) = (*start, *closing);
+                        let name = std::mem::take(name);
+                        self.end_tag(start, name, closing, out);
+                    }
+                    _ => {}
+                }
+            }
+            State::Comment { dashes } => match b {
+                b'-' => *dashes += 1,
+                b'>' if *dashes >= 2 => self.state = State::Text,
+                _ => *dashes = 0,
+            },
+            State::Hidden { name, tail } => {
+                tail.push(b.to_ascii_lowercase());
+                if tail.len() > name.len() + 2 {
+                    tail.remove(0);
+                }
+
+                if tail.starts_with(b"</") && tail[2..] == name[..] {
+                    let start = pos + 1 - tail.len() as u64;
+                    let name = std::mem::take(name);
+                    self.state =
+                        State::Tag { start, name, closing: true, named: true, quote: None };
+                }
+            }
+            State::Reference { start, name } => {
+                let start = *start;
+                if b == b';' {
+                    if let Some(c) = decode(name) {
+                        out.extend(c.encode_utf8(&mut [0; 4]).as_bytes(), start);
+                    } else {
+                        out.push(b'&', start);
+                        out.extend(name, start + 1);
+                        out.push(b';', pos);
+                    }
+                    self.state = State::Text;
+                } else if (b.is_ascii_alphanumeric() || b == b'#') && name.len() < MAX_REFERENCE {
+                    name.push(b);
+                } else {
+                    // Not a reference after all.
+                    out.push(b'&', start);
+                    out.extend(name, start + 1);
+                    self.state = State::Text;
+                    self.feed(b, pos, out);
+                }
+            }
+        }
+    }
+
+    fn end_tag(&mut self, start: u64, name: Vec<u8>, closing: bool, out: &mut Output) {
+        if BLOCKS.contains(&name.as_slice()) {
+            out.push(b'\n', start);
+        }
+
+        self.state = if !closing && HIDDEN.contains(&name.as_slice()) {
+            State::Hidden { name, tail: vec![] }
+        } else {
+            State::Text
+        };
+    }
+}
+
+impl Syntax for Html {
+    fn line(&mut self, line: &[u8], start: u64, out: &mut Output) {
+        for (i, &b) in line.iter().enumerate() {
+            self.feed(b, start + i as u64, out);
+        }
+    }
+
+    fn finish(&mut self, out: &mut Output) {
+        match std::mem::take(&mut self.state) {
+            State::Open(start) => out.push(b'<', start),
+            State::Reference { start, name } => {
+                out.push(b'&', start);
+                out.extend(&name, start + 1);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Decode the name of a character reference, without the leading `&` and trailing `;`.
+fn decode(name: &[u8]) -> Option<char> {
+    let name = std::str::from_utf8(name).ok()?;
+
+    if let Some(number) = name.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => number.parse(),
+        }
+        .ok()?;
+
+        return Some(char::from_u32(code).filter(|&c| c != '\0').unwrap_or('\u{fffd}'));
+    }
+
+    NAMED.iter().find(|&&(n, _)| n == name).map(|&(_, c)| c)
+}
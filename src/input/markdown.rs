@@ -0,0 +1,251 @@
+//! Streaming the visible text of Markdown.
+//!
+//! Markers of headings, quotes and lists are removed from the start of lines, as is inline
+//! formatting. Links and images are replaced by their label, code is kept as it is and inline HTML
+//! is handled like in HTML documents. Like the other formats, this is no full parser but covers
+//! the common syntax of [CommonMark][commonmark].
+//!
+//! [commonmark]: https://commonmark.org
+
+use super::html::Html;
+use super::markup::Syntax;
+use super::offsets::Output;
+
+#[derive(Debug, Default)]
+pub struct Markdown {
+    html: Html,
+    /// Character and length of the fence of the current code block.
+    fence: Option<(u8, usize)>,
+}
+
+impl Markdown {
+    /// Convert inline content, which starts at `start` within the source.
+    fn inline(&mut self, bytes: &[u8], start: u64, out: &mut Output) {
+        let mut brackets = 0_usize;
+        let mut code = false;
+        let mut i = 0;
+
+        while i < bytes.len() {
+            let b = bytes[i];
+            let pos = start + i as u64;
+            let prev = i.checked_sub(1).map(|i| bytes[i]);
+            let next = bytes.get(i + 1).copied();
+            i += 1;
+
+            // Attributes of inline HTML may contain anything.
+            if !self.html.is_text() {
+                self.html.feed(b, pos, out);
+                continue;
+            }
+
+            if code {
+                if b == b'`' {
+                    i += run(&bytes[i..], b'`');
+                    code = false;
+                } else {
+                    out.push(b, pos);
+                }
+                continue;
+            }
+
+            match b {
+                b'\\' if next.is_some_and(|n| n.is_ascii_punctuation()) => {
+                    out.push(bytes[i], pos + 1);
+                    i += 1;
+                }
+                b'`' => {
+                    i += run(&bytes[i..], b'`');
+                    code = true;
+                }
+                b'*' | b'~' => {}
+                b'_' if !(prev.is_some_and(is_word) && next.is_some_and(is_word)) => {}
+                b'!' if next == Some(b'[') => {}
+                b'[' => brackets += 1,
+                b']' if brackets > 0 => {
+                    brackets -= 1;
+                    // Skip the destination of links and the label of reference links.
+                    let close = match next {
+                        Some(b'(') => closing(&bytes[i..], b'(', b')'),
+                        Some(b'[') => closing(&bytes[i..], b'[', b']'),
+                        _ => None,
+                    };
+                    if let Some(close) = close {
+                        i += close + 1;
+                    }
+                }
+                // Cells of tables.
+                b'|' => out.push(b' ', pos),
+                b'<' => match autolink(&bytes[i..]) {
+                    Some(len) => {
+                        out.extend(&bytes[i..i + len], pos + 1);
+                        i += len + 1;
+                    }
+                    None => self.html.feed(b, pos, out),
+                },
+                _ => self.html.feed(b, pos, out),
+            }
+        }
+    }
+}
+
+impl Syntax for Markdown {
+    fn line(&mut self, line: &[u8], start: u64, out: &mut Output) {
+        let content = line.strip_suffix(b"\n").unwrap_or(line);
+        let content = content.strip_suffix(b"\r").unwrap_or(content);
+        let eol = start + content.len() as u64;
+        let trimmed = content.trim_ascii_start();
+        let indent = content.len() - trimmed.len();
+
+        // Code blocks are kept as they are, but without their fences.
+        if let Some((c, len)) = self.fence {
+            if run(trimmed, c) >= len && trimmed[run(trimmed, c)..].trim_ascii().is_empty() {
+                self.fence = None;
+                out.extend(&line[content.len()..], eol);
+            } else {
+                out.extend(line, start);
+            }
+            return;
+        }
+
+        if let Some(fence) = fence(trimmed).filter(|_| indent < 4) {
+            self.fence = Some(fence);
+            out.extend(&line[content.len()..], eol);
+            return;
+        }
+
+        if is_break(trimmed) || is_definition(trimmed) || is_delimiter_row(trimmed) {
+            out.extend(&line[content.len()..], eol);
+            return;
+        }
+
+        let (skip, heading) = prefix(content);
+        let mut end = content.len();
+        if heading {
+            // Remove the optional closing sequence of headings.
+            let text = content[skip..].trim_ascii_end();
+            let hashes = text.iter().rev().take_while(|&&b| b == b'#').count();
+            let rest = &text[..text.len() - hashes];
+            end = skip
+                + if hashes > 0
+                    && (rest.is_empty() || rest.ends_with(b" ") || rest.ends_with(b"\t"))
+                {
+                    rest.trim_ascii_end().len()
+                } else {
+                    text.len()
+                };
+        }
+
+        self.inline(&content[skip..end], start + skip as u64, out);
+        for (i, &b) in line[content.len()..].iter().enumerate() {
+            self.html.feed(b, eol + i as u64, out);
+        }
+    }
+
+    fn finish(&mut self, out: &mut Output) {
+        self.html.finish(out);
+    }
+}
+
+/// Length of the markers of quotes, headings and list items at the start of the line, and whether
+/// the line is a heading.
+fn prefix(line: &[u8]) -> (usize, bool) {
+    let spaces = |i: usize| i + line[i..].iter().take_while(|b| matches!(b, b' ' | b'\t')).count();
+    let mut i = spaces(0);
+
+    while line.get(i) == Some(&b'>') {
+        i = spaces(i + 1);
+    }
+
+    let marker = |len: usize| matches!(line.get(i + len), None | Some(b' ' | b'\t'));
+
+    let hashes = run(&line[i..], b'#');
+    if (1..=6).contains(&hashes) && marker(hashes) {
+        return (spaces(i + hashes), true);
+    }
+
+    if matches!(line.get(i), Some(b'-' | b'*' | b'+')) && marker(1) {
+        i = spaces(i + 1);
+    } else {
+        let digits = line[i..].iter().take_while(|b| b.is_ascii_digit()).count();
+        if (1..10).contains(&digits)
+            && matches!(line.get(i + digits), Some(b'.' | b')'))
+            && marker(digits + 1)
+        {
+            i = spaces(i + digits + 1);
+        }
+    }
+
+    // Check boxes of task lists.
+    if matches!(&line[i..], [b'[', b' ' | b'x' | b'X', b']', b' ', ..]) {
+        i = spaces(i + 3);
+    }
+
+    (i, false)
+}
+
+/// The character and length of the fence opening a code block.
+fn fence(line: &[u8]) -> Option<(u8, usize)> {
+    let c = *line.first().filter(|&&c| c == b'`' || c == b'~')?;
+    let len = run(line, c);
+    (len >= 3).then_some((c, len))
+}
+
+/// Thematic breaks and underlines of headings.
+fn is_break(line: &[u8]) -> bool {
+    let Some(&c) = line.first().filter(|&&c| matches!(c, b'-' | b'*' | b'_' | b'=')) else {
+        return false;
+    };
+
+    line.iter().all(|&b| b == c || b.is_ascii_whitespace())
+        && (c == b'=' || line.iter().filter(|b| !b.is_ascii_whitespace()).count() >= 3)
+}
+
+/// Definitions of link references like `[id]: https://example.com`.
+fn is_definition(line: &[u8]) -> bool {
+    line.first() == Some(&b'[')
+        && line
+            .iter()
+            .position(|&b| b == b']')
+            .is_some_and(|i| i > 1 && line.get(i + 1) == Some(&b':'))
+}
+
+/// The row between the header and body of a table.
+fn is_delimiter_row(line: &[u8]) -> bool {
+    line.contains(&b'|')
+        && line.contains(&b'-')
+        && line.iter().all(|b| matches!(b, b'|' | b':' | b'-' | b' ' | b'\t'))
+}
+
+/// The length of the URL or email address of an autolink, right after its `<`.
+fn autolink(bytes: &[u8]) -> Option<usize> {
+    let len = bytes.iter().position(|&b| b == b'>')?;
+    let inner = &bytes[..len];
+    let valid = !inner.is_empty()
+        && !inner.iter().any(u8::is_ascii_whitespace)
+        && (inner.windows(3).any(|w| w == b"://") || inner.contains(&b'@'));
+    valid.then_some(len)
+}
+
+/// Position of the bracket that closes the one at the start.
+fn closing(bytes: &[u8], open: u8, close: u8) -> Option<usize> {
+    let mut depth = 0_usize;
+    for (i, &b) in bytes.iter().enumerate() {
+        if b == open {
+            depth += 1;
+        } else if b == close {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+fn run(bytes: &[u8], c: u8) -> usize {
+    bytes.iter().take_while(|&&b| b == c).count()
+}
+
+fn is_word(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || !b.is_ascii()
+}
@@ -0,0 +1,80 @@
+//! A [`Read`] adapter that streams the visible text of a markup language line by line.
+
+use std::io::{self, BufRead, Read};
+use std::sync::Arc;
+
+use super::offsets::{Offsets, Output};
+
+/// A markup language, converting its source into visible text.
+pub trait Syntax {
+    /// Convert the next line, including its line break, that starts at `start` within the source.
+    fn line(&mut self, line: &[u8], start: u64, out: &mut Output);
+
+    /// Flush anything still pending at the end of the source.
+    fn finish(&mut self, out: &mut Output);
+}
+
+pub struct Markup<R, S> {
+    input: R,
+    syntax: S,
+    out: Output,
+    /// Amount of bytes of the output that were already read.
+    pos: usize,
+    line: Vec<u8>,
+    /// Position of the next line within the source.
+    offset: u64,
+    offsets: Option<Arc<Offsets>>,
+    done: bool,
+}
+
+impl<R: BufRead, S: Syntax> Markup<R, S> {
+    /// Create a reader for the source. Positions of the visible text are recorded in `offsets`, if
+    /// given.
+    pub fn new(input: R, syntax: S, offsets: Option<Arc<Offsets>>) -> Self {
+        Self {
+            input,
+            syntax,
+            out: Output::new(offsets.is_some()),
+            pos: 0,
+            line: vec![],
+            offset: 0,
+            offsets,
+            done: false,
+        }
+    }
+}
+
+impl<R: BufRead, S: Syntax> Read for Markup<R, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.out.buf.len() {
+            if self.done {
+                return Ok(0);
+            }
+
+            self.out.buf.clear();
+            self.pos = 0;
+            self.line.clear();
+
+            match self.input.read_until(b'\n', &mut self.line)? {
+                0 => {
+                    self.syntax.finish(&mut self.out);
+                    self.done = true;
+                }
+                n => {
+                    self.syntax.line(&self.line, self.offset, &mut self.out);
+                    self.offset += n as u64;
+                }
+            }
+
+            // Anchors must be known before the text they belong to is read.
+            if let (Some(offsets), Some(anchors)) = (&self.offsets, &mut self.out.anchors) {
+                offsets.extend(anchors);
+            }
+        }
+
+        let n = buf.len().min(self.out.buf.len() - self.pos);
+        buf[..n].copy_from_slice(&self.out.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
@@ -0,0 +1,142 @@
+//! Reading the pages of an XML dump of a wiki.
+//!
+//! Every `<page>` element becomes a document, starting with a separator line that holds the page
+//! title after a form feed, followed by the text of the page. Everything else in the dump, like
+//! revision details and contributors, is skipped.
+
+use std::io::{self, BufRead, Read};
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+use super::wikitext::Stripper;
+
+/// Pattern of the separator lines, capturing the title of the page.
+pub const SEPARATOR: &str = "^\x0c(?P<title>.*)$";
+
+/// The element whose text is currently read.
+#[derive(Clone, Copy)]
+enum Field {
+    Title,
+    Text,
+}
+
+/// A [`Read`] adapter that turns an XML dump of a wiki into plain text, one page after another.
+pub struct MediaWiki<R: BufRead> {
+    reader: Reader<R>,
+    buf: Vec<u8>,
+    stripper: Option<Stripper>,
+    /// The current page, converted into text.
+    page: Vec<u8>,
+    /// Amount of bytes of the current page that were already read.
+    pos: usize,
+    done: bool,
+}
+
+impl<R: BufRead> MediaWiki<R> {
+    /// Create a reader for the dump, removing wiki markup from the text of pages if `strip` is set.
+    pub fn new(input: R, strip: bool) -> Self {
+        Self {
+            reader: Reader::from_reader(input),
+            buf: vec![],
+            stripper: strip.then(Stripper::new),
+            page: vec![],
+            pos: 0,
+            done: false,
+        }
+    }
+
+    /// Read the next page into the buffer, or mark the reader as done at the end of the dump.
+    fn next_page(&mut self) -> io::Result<()> {
+        let mut title = String::new();
+        let mut text = String::new();
+        let mut field = None;
+        let mut in_page = false;
+
+        loop {
+            self.buf.clear();
+            let event = self.reader.read_event(&mut self.buf).map_err(invalid)?;
+
+            match event {
+                Event::Start(e) => match e.name() {
+                    b"page" => {
+                        in_page = true;
+                        title.clear();
+                        text.clear();
+                    }
+                    b"title" if in_page => field = Some(Field::Title),
+                    b"text" if in_page => field = Some(Field::Text),
+                    _ => {}
+                },
+                Event::Text(e) => {
+                    if let Some(field) = field {
+                        let s = e.unescape_and_decode(&self.reader).map_err(invalid)?;
+                        match field {
+                            Field::Title => title.push_str(&s),
+                            Field::Text => text.push_str(&s),
+                        }
+                    }
+                }
+                Event::CData(e) => {
+                    if let Some(Field::Text) = field {
+                        text.push_str(&String::from_utf8_lossy(&e));
+                    }
+                }
+                Event::End(e) => match e.name() {
+                    b"title" | b"text" => field = None,
+                    b"page" if in_page => {
+                        self.render(&title, &text);
+                        return Ok(());
+                    }
+                    _ => {}
+                },
+                Event::Eof => {
+                    self.done = true;
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn render(&mut self, title: &str, text: &str) {
+        // Form feeds only ever appear in separator lines.
+        let clean = |s: &str| s.replace('\x0c', " ");
+        let text = match &self.stripper {
+            Some(stripper) => stripper.strip(text),
+            None => text.to_owned(),
+        };
+
+        self.page.clear();
+        self.pos = 0;
+        self.page.push(b'\x0c');
+        self.page.extend_from_slice(clean(title).replace(['\r', '\n'], " ").as_bytes());
+        self.page.push(b'\n');
+        self.page.extend_from_slice(clean(&text).as_bytes());
+        if !self.page.ends_with(b"\n") {
+            self.page.push(b'\n');
+        }
+    }
+}
+
+impl<R: BufRead> Read for MediaWiki<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.page.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.page.clear();
+            self.pos = 0;
+            self.next_page()?;
+        }
+
+        let n = buf.len().min(self.page.len() - self.pos);
+        buf[..n].copy_from_slice(&self.page[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+fn invalid(e: quick_xml::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
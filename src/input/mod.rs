@@ -0,0 +1,191 @@
+//! Readers that turn articles in other formats into the plain text that is searched.
+//!
+//! Each reader wraps the original file as a [`Read`] adapter, so everything after it works on
+//! plain text just like before.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use regex::Regex;
+
+use crate::progress::Progress;
+
+mod html;
+mod markdown;
+mod markup;
+mod mediawiki;
+mod offsets;
+mod range;
+mod records;
+mod wikitext;
+
+pub use html::Html;
+pub use markdown::Markdown;
+pub use markup::Markup;
+pub use mediawiki::MediaWiki;
+pub use offsets::Offsets;
+pub use range::{ByteRange, InvalidRange};
+pub use records::{Field, Groups, Records};
+
+/// Format of the article.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum InputFormat {
+    /// Plain text, taken as is.
+    #[default]
+    Text,
+    /// An XML dump of a wiki, like the `pages-articles.xml` dumps of Wikipedia.
+    MediaWiki,
+    /// HTML, of which only the visible text is taken.
+    Html,
+    /// Markdown, of which only the visible text is taken.
+    Markdown,
+}
+
+impl InputFormat {
+    /// Pattern of the lines that start a new document in the converted text, capturing its title.
+    pub fn separator(self) -> Option<Regex> {
+        match self {
+            Self::Text | Self::Html | Self::Markdown => None,
+            Self::MediaWiki => Some(Regex::new(mediawiki::SEPARATOR).unwrap()),
+        }
+    }
+}
+
+impl FromStr for InputFormat {
+    type Err = UnknownInputFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "mediawiki" => Ok(Self::MediaWiki),
+            "html" => Ok(Self::Html),
+            "markdown" => Ok(Self::Markdown),
+            _ => Err(UnknownInputFormat(s.to_owned())),
+        }
+    }
+}
+
+/// Error returned when parsing an unsupported article format.
+#[derive(Debug)]
+pub struct UnknownInputFormat(String);
+
+impl fmt::Display for UnknownInputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown input format `{}`, expected one of `text`, `mediawiki`, `html` or `markdown`",
+            self.0
+        )
+    }
+}
+
+impl Error for UnknownInputFormat {}
+
+/// Error returned when the fields of records are given inconsistently.
+#[derive(Debug)]
+pub enum InvalidFields {
+    /// Records are either JSON or CSV, not both.
+    Both,
+    /// Grouping needs records.
+    GroupWithoutField,
+    /// Converting the text of records would move them away from where their group starts.
+    GroupWithFormat,
+}
+
+impl fmt::Display for InvalidFields {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Both => "a JSON field and a CSV column can't be given at the same time",
+            Self::GroupWithoutField => "grouping needs a JSON field or CSV column to count",
+            Self::GroupWithFormat => "grouping only works on fields of plain text",
+        })
+    }
+}
+
+impl Error for InvalidFields {}
+
+/// How to turn the article into the plain text that is searched.
+#[derive(Debug, Default)]
+pub struct Input {
+    pub format: InputFormat,
+    /// Remove wiki markup from the pages of wiki dumps.
+    pub strip: bool,
+    /// Only take this field of each record. The format then applies to the text of the field.
+    pub field: Option<Field>,
+    /// Group records by this field.
+    pub group_by: Option<String>,
+    /// Record the positions of the text within the original article, for HTML and Markdown.
+    /// Other formats leave them untouched.
+    pub offsets: Option<Arc<Offsets>>,
+    /// Collect counts per group of records.
+    pub groups: Option<Arc<Groups>>,
+    /// Only read this byte range of the article, as aligned by [`ByteRange::align`].
+    pub range: Option<(u64, u64)>,
+    /// Count the bytes read from the article.
+    pub progress: Option<Arc<Progress>>,
+}
+
+impl Input {
+    /// Number of bytes that are read from the article, if known.
+    pub fn len(&self, path: impl AsRef<Path>) -> io::Result<Option<u64>> {
+        if let Some((start, end)) = self.range {
+            return Ok(Some(end - start));
+        }
+
+        // Pipes and other special files don't know their size in advance.
+        let metadata = fs::metadata(path)?;
+        Ok(metadata.is_file().then_some(metadata.len()))
+    }
+
+    /// Open the article and convert it into plain text.
+    pub fn open(&self, path: impl AsRef<Path>) -> io::Result<BufReader<Box<dyn Read + Send>>> {
+        let path = path.as_ref();
+        let mut file = File::open(path)?;
+        let mut header = None;
+        let file: Box<dyn Read + Send> = match self.range {
+            Some((mut start, end)) => {
+                // Records from within a CSV file still need the header at its start.
+                if start > 0 && matches!(self.field, Some(Field::Csv(_))) {
+                    let len = records::csv_header(File::open(path)?)?;
+                    header = Some(File::open(path)?.take(len));
+                    start = start.max(len);
+                }
+                file.seek(SeekFrom::Start(start))?;
+                Box::new(file.take(end.saturating_sub(start)))
+            }
+            None => Box::new(file),
+        };
+        let file = match &self.progress {
+            Some(progress) => Box::new(progress.reader(file)),
+            None => file,
+        };
+        let file = match header {
+            Some(header) => Box::new(header.chain(file)),
+            None => file,
+        };
+
+        let source: Box<dyn BufRead + Send> = match &self.field {
+            Some(field) => Box::new(BufReader::new(Records::new(
+                BufReader::new(file),
+                field,
+                self.group_by.as_deref(),
+                self.groups.clone(),
+            )?)),
+            None if self.format == InputFormat::Text => return Ok(BufReader::new(file)),
+            None => Box::new(BufReader::new(file)),
+        };
+
+        let offsets = self.offsets.clone();
+        Ok(BufReader::new(match self.format {
+            InputFormat::Text => Box::new(source),
+            InputFormat::MediaWiki => Box::new(MediaWiki::new(source, self.strip)),
+            InputFormat::Html => Box::new(Markup::new(source, Html::default(), offsets)),
+            InputFormat::Markdown => Box::new(Markup::new(source, Markdown::default(), offsets)),
+        }))
+    }
+}
@@ -0,0 +1,89 @@
+//! Mapping byte positions within converted text back to the original article.
+
+use std::sync::Mutex;
+
+/// The points where the converted text stops following the original byte by byte, for example after
+/// a removed tag. Every byte between two anchors maps to the original one by one.
+#[derive(Debug)]
+pub struct Offsets {
+    /// Position within the article where reading started.
+    base: u64,
+    /// Pairs of converted and original positions, sorted by both.
+    anchors: Mutex<Vec<(u64, u64)>>,
+}
+
+impl Default for Offsets {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl Offsets {
+    /// Create a mapping for an article that is read starting at `base`.
+    pub fn new(base: u64) -> Self {
+        Self { base, anchors: Mutex::new(vec![(0, base)]) }
+    }
+
+    /// Add anchors found while converting the next part of the article, relative to the base.
+    pub(super) fn extend(&self, anchors: &mut Vec<(u64, u64)>) {
+        if !anchors.is_empty() {
+            let shifted = anchors.drain(..).map(|(c, o)| (c, o + self.base));
+            self.anchors.lock().unwrap().extend(shifted);
+        }
+    }
+
+    /// Map the byte range of converted text to the original article. The end is taken right after
+    /// the last byte of the range, so markup following a match isn't included.
+    pub fn original(&self, start: u64, end: u64) -> (u64, u64) {
+        let anchors = self.anchors.lock().unwrap();
+        let map = |pos: u64| {
+            let (converted, original) = anchors[anchors.partition_point(|&(c, _)| c <= pos) - 1];
+            original + (pos - converted)
+        };
+
+        if start == end {
+            (map(start), map(start))
+        } else {
+            (map(start), map(end - 1) + 1)
+        }
+    }
+}
+
+/// Converted text, keeping track of where each byte came from.
+#[derive(Debug, Default)]
+pub struct Output {
+    pub(super) buf: Vec<u8>,
+    /// Total length of the converted text so far.
+    len: u64,
+    /// Original position that the next byte has if no markup was removed in between.
+    next: u64,
+    /// New anchors, if tracked at all.
+    pub(super) anchors: Option<Vec<(u64, u64)>>,
+}
+
+impl Output {
+    pub fn new(track: bool) -> Self {
+        Self { anchors: track.then(Vec::new), ..Self::default() }
+    }
+
+    /// Append a byte that was found at the given position of the original article. Bytes added in
+    /// place of markup get the position of the markup.
+    pub fn push(&mut self, byte: u8, original: u64) {
+        if original != self.next {
+            if let Some(anchors) = &mut self.anchors {
+                anchors.push((self.len, original));
+            }
+        }
+
+        self.buf.push(byte);
+        self.len += 1;
+        self.next = original + 1;
+    }
+
+    /// Append bytes that start at the given position of the original article.
+    pub fn extend(&mut self, bytes: &[u8], original: u64) {
+        for (i, &b) in bytes.iter().enumerate() {
+            self.push(b, original + i as u64);
+        }
+    }
+}
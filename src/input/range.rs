@@ -0,0 +1,98 @@
+//! Reading only a byte range of the article, so a large article can be split across processes.
+//!
+//! Ranges are aligned to lines: a range covers every line that starts within it, including the
+//! rest of its last line. Ranges that follow on each other therefore cover every line exactly once
+//! and so are matches within a line. Matches that reach across lines, possible with a line break
+//! in an extended word or a regular expression, are lost where two ranges meet. Only plain text and
+//! records can be split like this, as markup elements may span lines.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::size::{self, InvalidSize};
+
+/// A byte range of the article, given as `START:END`. Either side may be left out to mean the
+/// start or end of the article.
+#[derive(Clone, Copy, Debug)]
+pub struct ByteRange {
+    start: u64,
+    end: Option<u64>,
+}
+
+impl ByteRange {
+    /// Find the range of whole lines within the article.
+    pub fn align(self, path: impl AsRef<Path>) -> io::Result<(u64, u64)> {
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+
+        let start = line_start(&mut file, self.start.min(len))?;
+        let end = match self.end {
+            Some(end) => line_start(&mut file, end.min(len))?,
+            None => len,
+        };
+
+        Ok((start, end.max(start)))
+    }
+}
+
+/// The start of the first line at or after `pos`.
+fn line_start(file: &mut File, pos: u64) -> io::Result<u64> {
+    if pos == 0 {
+        return Ok(0);
+    }
+
+    // A line starts right at the position if the previous byte ends a line.
+    file.seek(SeekFrom::Start(pos - 1))?;
+    let skipped = BufReader::new(file).skip_until(b'\n')?;
+    Ok(pos - 1 + skipped as u64)
+}
+
+impl FromStr for ByteRange {
+    type Err = InvalidRange;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s.split_once(':').ok_or_else(|| InvalidRange::Format(s.to_owned()))?;
+        let parse = |s: &str| if s.is_empty() { Ok(None) } else { size::parse(s).map(Some) };
+
+        let start = parse(start)?.unwrap_or_default();
+        let end = parse(end)?;
+        if end.is_some_and(|end| end < start) {
+            return Err(InvalidRange::Reversed(s.to_owned()));
+        }
+
+        Ok(Self { start, end })
+    }
+}
+
+/// Error returned for an invalid byte range.
+#[derive(Debug)]
+pub enum InvalidRange {
+    Format(String),
+    Reversed(String),
+    Size(InvalidSize),
+    /// Only plain text and records can be split at lines.
+    Markup,
+}
+
+impl From<InvalidSize> for InvalidRange {
+    fn from(e: InvalidSize) -> Self {
+        Self::Size(e)
+    }
+}
+
+impl fmt::Display for InvalidRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Format(s) => write!(f, "invalid range `{s}`, expected `START:END`"),
+            Self::Reversed(s) => write!(f, "invalid range `{s}`, the end lies before the start"),
+            Self::Size(e) => e.fmt(f),
+            Self::Markup => f.write_str("a range can only be read of plain text or records"),
+        }
+    }
+}
+
+impl Error for InvalidRange {}
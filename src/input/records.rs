@@ -0,0 +1,247 @@
+//! Reading a single field of each record in JSON Lines or CSV files.
+//!
+//! The text of the chosen field of every record becomes a line of its own, so the rest of the
+//! program only ever sees that field. Records can additionally be grouped by another field, in
+//! which case the position where each record starts within the extracted text is recorded, so
+//! matches can be attributed to the group of their record.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Read};
+use std::sync::{Arc, Mutex};
+
+use indexmap::IndexSet;
+use serde_json::Value;
+
+/// The field that holds the text of each record.
+#[derive(Clone, Debug)]
+pub enum Field {
+    /// Dot separated path of keys or array indices of a JSON object.
+    Json(String),
+    /// Name of a column of a CSV file with header.
+    Csv(String),
+}
+
+/// Counts of words per group of records.
+#[derive(Debug, Default)]
+pub struct Groups {
+    /// Names of all groups, in the order they were first found.
+    names: Mutex<IndexSet<String>>,
+    /// Positions within the extracted text where a record of another group starts.
+    starts: Mutex<Vec<(u64, usize)>>,
+    /// Counts by group and word.
+    counts: Mutex<HashMap<(usize, usize), u64>>,
+}
+
+impl Groups {
+    /// Mark that a record of the given group starts at a position of the extracted text.
+    fn start(&self, pos: u64, name: String) {
+        let group = self.names.lock().unwrap().insert_full(name).0;
+        let mut starts = self.starts.lock().unwrap();
+        if starts.last().is_none_or(|&(_, g)| g != group) {
+            starts.push((pos, group));
+        }
+    }
+
+    /// Add the matches of a chunk, given by their byte position within the chunk and the index of
+    /// their word.
+    pub fn add(&self, offset: u64, matches: &[(usize, usize)]) {
+        let mut counts = HashMap::<_, u64>::new();
+        {
+            let starts = self.starts.lock().unwrap();
+            for &(pos, id) in matches {
+                let pos = offset + pos as u64;
+                let Some(i) = starts.partition_point(|&(start, _)| start <= pos).checked_sub(1)
+                else {
+                    continue;
+                };
+                *counts.entry((starts[i].1, id)).or_default() += 1;
+            }
+        }
+
+        let mut total = self.counts.lock().unwrap();
+        for (key, n) in counts {
+            *total.entry(key).or_default() += n;
+        }
+    }
+
+    /// The name of every group with the count of each word found in its records, sorted by word.
+    pub fn finish(&self) -> Vec<(String, Vec<(usize, u64)>)> {
+        let names = self.names.lock().unwrap();
+        let mut rows: Vec<_> = names.iter().map(|name| (name.clone(), vec![])).collect();
+
+        for (&(group, id), &count) in self.counts.lock().unwrap().iter() {
+            rows[group].1.push((id, count));
+        }
+        for (_, row) in &mut rows {
+            row.sort_unstable();
+        }
+
+        rows
+    }
+}
+
+/// A single record, with the text of the chosen field and the name of its group, if any.
+struct Record {
+    text: Option<String>,
+    group: Option<String>,
+}
+
+/// A source of records.
+trait Source {
+    fn next(&mut self) -> io::Result<Option<Record>>;
+}
+
+struct Jsonl<R> {
+    input: R,
+    field: Vec<String>,
+    group: Option<Vec<String>>,
+    line: String,
+    number: u64,
+}
+
+impl<R: BufRead> Source for Jsonl<R> {
+    fn next(&mut self) -> io::Result<Option<Record>> {
+        loop {
+            self.line.clear();
+            if self.input.read_line(&mut self.line)? == 0 {
+                return Ok(None);
+            }
+            self.number += 1;
+
+            if self.line.trim().is_empty() {
+                continue;
+            }
+
+            let value: Value = serde_json::from_str(&self.line).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {e}", self.number))
+            })?;
+
+            return Ok(Some(Record {
+                text: lookup(&value, &self.field),
+                group: self.group.as_ref().map(|g| lookup(&value, g).unwrap_or_default()),
+            }));
+        }
+    }
+}
+
+/// The text of the value at the path. Anything but strings is taken in its JSON representation.
+fn lookup(value: &Value, path: &[String]) -> Option<String> {
+    let value = path.iter().try_fold(value, |v, key| match v {
+        Value::Object(map) => map.get(key),
+        Value::Array(values) => values.get(key.parse::<usize>().ok()?),
+        _ => None,
+    })?;
+
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        v => Some(v.to_string()),
+    }
+}
+
+/// Length of the header of a CSV file, up to where its first record starts.
+pub fn csv_header(input: impl Read) -> io::Result<u64> {
+    let mut input = csv::ReaderBuilder::new().flexible(true).from_reader(input);
+    input.headers()?;
+    Ok(input.position().byte())
+}
+
+struct Csv<R> {
+    input: csv::Reader<R>,
+    column: usize,
+    group: Option<usize>,
+    record: csv::StringRecord,
+}
+
+impl<R: Read> Csv<R> {
+    fn new(input: R, column: &str, group: Option<&str>) -> io::Result<Self> {
+        let mut input = csv::ReaderBuilder::new().flexible(true).from_reader(input);
+        let headers = input.headers()?;
+        let find = |name: &str| {
+            headers.iter().position(|h| h == name).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("no CSV column `{name}`"))
+            })
+        };
+
+        let column = find(column)?;
+        let group = group.map(find).transpose()?;
+        Ok(Self { input, column, group, record: csv::StringRecord::new() })
+    }
+}
+
+impl<R: Read> Source for Csv<R> {
+    fn next(&mut self) -> io::Result<Option<Record>> {
+        if !self.input.read_record(&mut self.record)? {
+            return Ok(None);
+        }
+
+        Ok(Some(Record {
+            text: self.record.get(self.column).map(str::to_owned),
+            group: self.group.map(|g| self.record.get(g).unwrap_or_default().to_owned()),
+        }))
+    }
+}
+
+/// A [`Read`] adapter that turns records into lines of text of a single field.
+pub struct Records {
+    source: Box<dyn Source + Send>,
+    groups: Option<Arc<Groups>>,
+    /// The text of the current record.
+    text: Vec<u8>,
+    /// Amount of bytes of the current record that were already read.
+    pos: usize,
+    /// Total length of the extracted text so far.
+    len: u64,
+}
+
+impl Records {
+    /// Create a reader for the field of each record. Records are grouped by the field `group` if
+    /// given, with counts collected in `groups`.
+    pub fn new(
+        input: impl BufRead + Send + 'static,
+        field: &Field,
+        group: Option<&str>,
+        groups: Option<Arc<Groups>>,
+    ) -> io::Result<Self> {
+        let path = |p: &str| p.split('.').map(str::to_owned).collect();
+        let source: Box<dyn Source + Send> = match field {
+            Field::Json(field) => Box::new(Jsonl {
+                input,
+                field: path(field),
+                group: group.map(path),
+                line: String::new(),
+                number: 0,
+            }),
+            Field::Csv(column) => Box::new(Csv::new(input, column, group)?),
+        };
+
+        Ok(Self { source, groups, text: vec![], pos: 0, len: 0 })
+    }
+}
+
+impl Read for Records {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.text.len() {
+            let Some(record) = self.source.next()? else {
+                return Ok(0);
+            };
+            let Some(text) = record.text else {
+                continue;
+            };
+
+            if let (Some(groups), Some(group)) = (&self.groups, record.group) {
+                groups.start(self.len, group);
+            }
+
+            self.text = text.into_bytes();
+            self.text.push(b'\n');
+            self.pos = 0;
+            self.len += self.text.len() as u64;
+        }
+
+        let n = buf.len().min(self.text.len() - self.pos);
+        buf[..n].copy_from_slice(&self.text[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
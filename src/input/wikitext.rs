@@ -0,0 +1,126 @@
+//! Removing the markup of wiki pages, leaving only the text a reader would see.
+//!
+//! This is no full wikitext parser. It handles the common markup well enough for counting words:
+//! templates, tables, references, comments and media are dropped, links are replaced by their label
+//! and formatting like bold text or headings is removed.
+
+use regex::Regex;
+
+/// Namespaces of links that don't show up as text.
+const HIDDEN: &[&str] = &["file", "image", "media", "category"];
+
+/// Removes wiki markup.
+pub struct Stripper {
+    comments: Regex,
+    refs: Regex,
+    external: Regex,
+    tags: Regex,
+    quotes: Regex,
+    headings: Regex,
+}
+
+impl Stripper {
+    pub fn new() -> Self {
+        Self {
+            comments: Regex::new(r"(?s)<!--.*?(?:-->|\z)").unwrap(),
+            refs: Regex::new(r"(?is)<ref\b[^>]*/>|<ref\b[^>]*>.*?</ref\s*>").unwrap(),
+            external: Regex::new(r"\[(?:https?:)?//[^\s\]]*\s*([^\]]*)\]").unwrap(),
+            tags: Regex::new(r"</?[a-zA-Z][^>]*>").unwrap(),
+            quotes: Regex::new(r"'{2,}").unwrap(),
+            headings: Regex::new(r"(?m)^=+[ \t]*(.*?)[ \t]*=+[ \t]*$").unwrap(),
+        }
+    }
+
+    /// Return the visible text of the wikitext.
+    pub fn strip(&self, text: &str) -> String {
+        let text = self.comments.replace_all(text, "");
+        let text = self.refs.replace_all(&text, "");
+        let text = templates(&text);
+        let text = links(&text);
+        let text = self.external.replace_all(&text, "$1");
+        let text = self.tags.replace_all(&text, "");
+        let text = self.quotes.replace_all(&text, "");
+        self.headings.replace_all(&text, "$1").into_owned()
+    }
+}
+
+/// Remove templates `{{...}}` and tables `{|...|}`, which may be nested in each other.
+fn templates(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut depth = 0_usize;
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        if rest.starts_with("{{") || rest.starts_with("{|") {
+            depth += 1;
+            rest = &rest[2..];
+        } else if depth > 0 && (rest.starts_with("}}") || rest.starts_with("|}")) {
+            depth -= 1;
+            rest = &rest[2..];
+        } else {
+            let c = rest.chars().next().unwrap();
+            if depth == 0 {
+                out.push(c);
+            }
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+
+    out
+}
+
+/// Replace internal links `[[target|label]]` by their label, or their target if they have none.
+/// Links to media and categories are removed entirely.
+fn links(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("[[") {
+        out.push_str(&rest[..start]);
+        rest = &rest[start + 2..];
+
+        let Some(end) = closing(rest) else {
+            // Unbalanced brackets are kept as they are.
+            out.push_str("[[");
+            continue;
+        };
+
+        // Captions of images may contain links themselves.
+        let inner = links(&rest[..end]);
+        rest = &rest[end + 2..];
+
+        let namespace = inner.split_once(':').map(|(ns, _)| ns.trim().to_lowercase());
+        if namespace.is_some_and(|ns| HIDDEN.contains(&ns.as_str())) {
+            continue;
+        }
+
+        let label = inner.rsplit_once('|').map_or(inner.as_str(), |(_, label)| label);
+        out.push_str(label.trim_start_matches(':'));
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// Find the `]]` that closes a link, skipping nested links.
+fn closing(text: &str) -> Option<usize> {
+    let mut depth = 0_usize;
+    let mut i = 0;
+
+    while i + 1 < text.len() {
+        match &text.as_bytes()[i..i + 2] {
+            b"[[" => {
+                depth += 1;
+                i += 2;
+            }
+            b"]]" if depth == 0 => return Some(i),
+            b"]]" => {
+                depth -= 1;
+                i += 2;
+            }
+            _ => i += 1,
+        }
+    }
+
+    None
+}
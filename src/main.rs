@@ -36,7 +36,7 @@
 //! ## Further details
 //!
 //! - Text can be overlapping, for example `textext` results in 2 counts for `text`, one for
-//! **text**ext and one for tex**text**.
+//!   **text**ext and one for tex**text**.
 //! - The words and article are considered case-sensitive. `Text` and `text` are not the same.
 //! - All input should be treated as ASCII text.
 //!
@@ -44,74 +44,871 @@
 #![forbid(unsafe_code)]
 #![deny(rust_2018_idioms, clippy::all, clippy::pedantic, clippy::cargo)]
 
+use std::collections::HashSet;
 use std::env;
 use std::error::Error;
+use std::fmt::Write;
 use std::fs;
-use std::io::BufReader;
-use std::sync::atomic::AtomicU64;
+use std::io::{self, BufReader, BufWriter, Read};
+use std::num::ParseIntError;
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
-use getopts::Options;
+use getopts::{Matches, Options};
 use indexmap::map::IndexMap;
+use regex::Regex;
+
+use crate::ahocorasick::{EarlyStop, Report, Settings};
+use crate::checkpoint::{Checkpoint, ResumeError};
+use crate::fold::{Folding, Normalization};
+use crate::input::{
+    ByteRange, Field, Groups, Input, InputFormat, InvalidFields, InvalidRange, Offsets,
+};
+use crate::memory::Budget;
+use crate::partial::{MergeError, Partial};
+use crate::progress::Progress;
+use crate::query::{Query, QueryError};
+use crate::size::InvalidSize;
+use crate::units::{Unit, UnitReport};
+use crate::vocabulary::Tokenizer;
+use crate::words::{Entry, Format, Kind, Word, WordList};
 
 mod aho_corasick;
 mod ahocorasick;
+mod checkpoint;
+mod chunks;
+mod cooccurrence;
+mod fold;
 mod fourtytwo;
+mod fuzzy;
+mod input;
+mod memory;
 mod naive;
+mod partial;
+mod progress;
+mod query;
+mod size;
+mod stats;
+mod threads;
+mod units;
+mod vocabulary;
+mod words;
 
 const OPT_NAIVE: &str = "naive";
 const OPT_FOURTYTWO: &str = "fourtytwo";
+const OPT_UNICODE_FOLD: &str = "unicode-fold";
+const OPT_NORMALIZE: &str = "normalize";
+const OPT_POSITIONS: &str = "positions";
+const OPT_WORDS_FORMAT: &str = "words-format";
+const OPT_GROUPS: &str = "groups";
+const OPT_DEDUPE: &str = "dedupe";
+const OPT_REPORT_DUPLICATES: &str = "report-duplicates";
+const OPT_EXPAND: &str = "expand";
+const OPT_MAX_EDITS: &str = "max-edits";
+const OPT_COOCCURRENCE: &str = "cooccurrence";
+const OPT_COOCCURRENCE_FORMAT: &str = "cooccurrence-format";
+const OPT_QUERY: &str = "query";
+const OPT_UNIT: &str = "unit";
+const OPT_DOC_SEPARATOR: &str = "doc-separator";
+const OPT_TERM_MATRIX: &str = "term-matrix";
+const OPT_ALL_WORDS: &str = "all-words";
+const OPT_SEPARATORS: &str = "separators";
+const OPT_MIN_COUNT: &str = "min-count";
+const OPT_SPILL: &str = "spill";
+const OPT_NGRAMS: &str = "ngrams";
+const OPT_PHRASES_FROM: &str = "phrases-from";
+const OPT_INPUT_FORMAT: &str = "input-format";
+const OPT_STRIP_MARKUP: &str = "strip-markup";
+const OPT_JSONL_FIELD: &str = "jsonl-field";
+const OPT_CSV_COLUMN: &str = "csv-column";
+const OPT_GROUP_BY: &str = "group-by";
+const OPT_RANGE: &str = "range";
+const OPT_PARTIAL: &str = "partial";
+const OPT_CHECKPOINT: &str = "checkpoint";
+const OPT_CHECKPOINT_INTERVAL: &str = "checkpoint-interval";
+const OPT_RESUME: &str = "resume";
+const OPT_PROGRESS: &str = "progress";
+const OPT_STATUS_INTERVAL: &str = "status-interval";
+const OPT_STATS: &str = "stats";
+const OPT_STATS_FORMAT: &str = "stats-format";
+const OPT_DOT: &str = "dot";
+const OPT_DFA: &str = "dfa";
+const OPT_THREADS: &str = "threads";
+const OPT_CHUNK_SIZE: &str = "chunk-size";
+const OPT_MAX_MEMORY: &str = "max-memory";
+const OPT_STOP_AT: &str = "stop-at";
+const OPT_ANY: &str = "any";
 const OPT_HELP: &str = "help";
 const OPT_VERSION: &str = "version";
 
-fn main() -> Result<(), Box<dyn Error>> {
-    // First parsing CLI input.
-    let mut opts = Options::new();
-    opts.optflag("n", OPT_NAIVE, "Use a naive custom implementation");
-    opts.optflag("f", OPT_FOURTYTWO, "Use the fastest algorithm in the world");
-    opts.optflag("h", OPT_HELP, "Print this help menu");
-    opts.optflag("v", OPT_VERSION, "Show the program version");
+/// Subcommand to merge partial-count files.
+const CMD_MERGE: &str = "merge";
+/// Subcommand to print the automata of a words file.
+const CMD_EXPLAIN: &str = "explain";
 
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("error: {e}");
+        process::exit(1);
+    }
+}
+
+/// Run the command given on the command line.
+fn run() -> Result<(), Box<dyn Error>> {
+    // First parsing CLI input.
+    let opts = options();
     let matches = opts.parse(env::args().skip(1))?;
 
     if matches.opt_present(OPT_VERSION) {
         print_version();
         return Ok(());
     }
+    threads::init(matches.opt_get(OPT_THREADS)?)?;
 
-    if matches.opt_present(OPT_HELP) || matches.free.len() != 2 {
+    if matches.free.first().is_some_and(|c| c == CMD_MERGE) && !matches.opt_present(OPT_HELP) {
+        return merge(&matches);
+    }
+    if matches.free.first().is_some_and(|c| c == CMD_EXPLAIN) && matches.free.len() == 2 {
+        return explain(&matches);
+    }
+
+    let all_words = matches.opt_present(OPT_ALL_WORDS)
+        || matches.opt_present(OPT_NGRAMS)
+        || matches.opt_present(OPT_PHRASES_FROM);
+    if matches.opt_present(OPT_HELP) || matches.free.len() != if all_words { 1 } else { 2 } {
         print_usage(&opts);
         return Ok(());
     }
 
+    let normalization = matches.opt_get::<Normalization>(OPT_NORMALIZE)?;
+    let folding = Folding::new(matches.opt_present(OPT_UNICODE_FOLD), normalization);
+
+    if all_words {
+        return count_all_words(&matches, folding);
+    }
+
+    let format =
+        matches.opt_get(OPT_WORDS_FORMAT)?.unwrap_or_else(|| Format::from_path(&matches.free[0]));
+
     // Then we need to open our input files for processing.
-    let words = String::from_utf8(fs::read(&matches.free[0])?)?;
-    let words: Vec<_> = words.split_terminator('\n').collect();
-    let words_map: IndexMap<_, _> = words.iter().map(|s| (*s, AtomicU64::default())).collect();
-
-    let article = fs::File::open(&matches.free[1])?;
-    let article = BufReader::new(article);
-
-    // Here is the core logic for counting words. Everything else is just preparation
-    // like parsing CLI options, opening the files and so on.
-    if matches.opt_present(OPT_NAIVE) {
-        naive::process(&words_map, article);
-    } else if matches.opt_present(OPT_FOURTYTWO) {
-        fourtytwo::process(&words_map, article);
+    let words_file = String::from_utf8(fs::read(&matches.free[0])?)?;
+    let budget = budget(&matches, words_file.len())?;
+    let words = words::parse(&words_file, format)?;
+    let words_map: IndexMap<_, _> =
+        words.words().iter().map(|w| (w, AtomicU64::default())).collect();
+
+    if matches.opt_present(OPT_REPORT_DUPLICATES) {
+        report_duplicates(&words);
+    }
+
+    let queries = queries(&matches, &words)?;
+    let unit = unit(&matches, !queries.is_empty())?;
+    let positions = matches.opt_present(OPT_POSITIONS);
+    let range = matches.opt_get::<ByteRange>(OPT_RANGE)?;
+    let range = range.map(|range| range.align(&matches.free[1])).transpose()?;
+    let offsets = positions.then(|| Arc::new(Offsets::new(range.map_or(0, |(start, _)| start))));
+    let groups = matches.opt_present(OPT_GROUP_BY).then(Arc::<Groups>::default);
+    let mut input = input(&matches, offsets.clone(), groups.clone())?;
+    input.range = range;
+    let words_hash = words_hash(&words_file, format, folding, &input);
+    let (checkpoint, lead_in) =
+        resume(&matches, &mut input, &words_hash, &words, &words_map, folding)?;
+    let progress = progress(&matches, &mut input, &matches.free[1])?;
+    let settings = Settings {
+        folding,
+        positions,
+        offsets: offsets.as_deref(),
+        expand: matches.opt_present(OPT_EXPAND),
+        max_edits: matches.opt_get(OPT_MAX_EDITS)?,
+        cooccurrence: matches.opt_get(OPT_COOCCURRENCE)?,
+        unit: unit.as_ref(),
+        queries: &queries,
+        term_matrix: matches.opt_present(OPT_TERM_MATRIX),
+        groups: groups.as_deref(),
+        checkpoint: checkpoint.as_ref(),
+        lead_in,
+        chunk_size: chunk_size(&matches)?,
+        budget: budget.as_ref(),
+        stop: early_stop(&matches)?,
+    };
+    let article = input.open(&matches.free[1])?;
+    let report = count(&matches, &words_map, article, settings, progress.as_deref())?;
+
+    if let Some(checkpoint) = &checkpoint {
+        checkpoint.finish()?;
+    }
+    if matches.opt_present(OPT_STATS) {
+        print_stats(&matches, &report)?;
+    }
+
+    // Like `grep -q`, only the exit status tells whether any word was found.
+    if settings.stop == Some(EarlyStop::Any) {
+        process::exit(i32::from(words_map.values().all(|n| n.load(Ordering::SeqCst) == 0)));
+    }
+
+    // Positions are printed while scanning, so there are no counts to report afterwards.
+    if settings.positions {
+        return Ok(());
+    }
+
+    if let Some(path) = matches.opt_str(OPT_PARTIAL) {
+        return write_partial(&path, words_hash, range, &matches.free[1], &words, &words_map);
+    }
+
+    if settings.cooccurrence.is_some() {
+        let format = matches.opt_get(OPT_COOCCURRENCE_FORMAT)?.unwrap_or_default();
+        cooccurrence::write(&report.cooccurrence, &word_ids(&words), format, io::stdout().lock())?;
+        return Ok(());
+    }
+
+    print_counts(&matches, &words, &words_map, &report, unit.as_ref(), &queries)?;
+
+    if let (Some(groups), Some(field)) = (&groups, matches.opt_str(OPT_GROUP_BY)) {
+        print_record_groups(&field, &groups.finish(), &word_ids(&words));
+    }
+
+    Ok(())
+}
+
+/// Count the words within the article, while reporting the progress if asked to.
+fn count<R: Read + Send>(
+    matches: &Matches,
+    words_map: &IndexMap<&Word<'_>, AtomicU64>,
+    article: BufReader<R>,
+    settings: Settings<'_>,
+    progress: Option<&Progress>,
+) -> Result<Report, Box<dyn Error>> {
+    let found = || words_map.values().map(|count| count.load(Ordering::Relaxed)).sum();
+    let default = !matches.opt_present(OPT_NAIVE) && !matches.opt_present(OPT_FOURTYTWO);
+    if (settings.budget.is_some() || settings.stop.is_some()) && !default {
+        eprintln!("warning: the memory limit and early stops only apply to the default algorithm");
+    }
+
+    progress::watch(progress, found, || {
+        // Here is the core logic for counting words. Everything else is just preparation
+        // like parsing CLI options, opening the files and so on.
+        Ok(if matches.opt_present(OPT_NAIVE) {
+            naive::process(words_map, article, settings.folding)?;
+            Report::default()
+        } else if matches.opt_present(OPT_FOURTYTWO) {
+            fourtytwo::process(words_map, article);
+            Report::default()
+        } else {
+            ahocorasick::process(words_map, article, settings)?
+        })
+    })
+}
+
+/// Print the statistics of the run on stderr, which only the default algorithm collects.
+fn print_stats(matches: &Matches, report: &Report) -> Result<(), Box<dyn Error>> {
+    let format = matches.opt_get(OPT_STATS_FORMAT)?.unwrap_or_default();
+    match &report.stats {
+        Some(stats) => stats.write(format, io::stderr().lock())?,
+        None => eprintln!("warning: statistics are only collected by the default algorithm"),
+    }
+    Ok(())
+}
+
+/// Set up the progress report of reading the article, if enabled.
+fn progress(
+    matches: &Matches,
+    input: &mut Input,
+    article: &str,
+) -> Result<Option<Arc<Progress>>, Box<dyn Error>> {
+    let status = matches.opt_get(OPT_STATUS_INTERVAL)?.map(Duration::from_secs);
+    let total = input.len(article)?;
+    input.progress = Progress::new(total, matches.opt_present(OPT_PROGRESS), status).map(Arc::new);
+    Ok(input.progress.clone())
+}
+
+/// All options the program understands.
+fn options() -> Options {
+    let mut opts = Options::new();
+    opts.optflag("n", OPT_NAIVE, "Use a naive custom implementation");
+    opts.optflag("f", OPT_FOURTYTWO, "Use the fastest algorithm in the world");
+    opts.optflag("u", OPT_UNICODE_FOLD, "Match case-insensitively with Unicode case folding");
+    opts.optopt("", OPT_NORMALIZE, "Normalize words and article before matching", "nfc|nfkc");
+    opts.optflag("p", OPT_POSITIONS, "Print the byte range of every match instead of the counts");
+    opts.optopt(
+        "",
+        OPT_WORDS_FORMAT,
+        "Format of the words file, guessed from the file extension by default",
+        "plain|extended|json|csv|yaml",
+    );
+    opts.optflag("g", OPT_GROUPS, "Print tables of the counts per word and per group");
+    opts.optflag("d", OPT_DEDUPE, "Print the count of duplicate words only once");
+    opts.optflag("", OPT_REPORT_DUPLICATES, "Report duplicate and empty words on stderr");
+    opts.optflag("e", OPT_EXPAND, "Print the strings that matched each wildcard");
+    opts.optopt("", OPT_MAX_EDITS, "Also count literal words with up to K typos separately", "K");
+    opts.optopt("", OPT_COOCCURRENCE, "Print pairs of words found within a window", "N[b|t|l]");
+    opts.optopt("", OPT_COOCCURRENCE_FORMAT, "Format of the co-occurrence pairs", "csv|json");
+    opts.optmulti("q", OPT_QUERY, "Count the units that satisfy a query, can be repeated", "QUERY");
+    opts.optopt(
+        "",
+        OPT_UNIT,
+        "Also count the units each word is found in, queries use lines by default",
+        "line|paragraph|document",
+    );
+    opts.optopt("", OPT_DOC_SEPARATOR, "Start a new document at lines matching this", "REGEX");
+    opts.optopt("", OPT_TERM_MATRIX, "Write the counts of every unit with TF-IDF as CSV", "FILE");
+    opts.optflag("a", OPT_ALL_WORDS, "Count every token of the article, without a words file");
+    opts.optopt("", OPT_SEPARATORS, "Split tokens on these characters and line breaks", "CHARS");
+    opts.optopt("", OPT_MIN_COUNT, "Leave out tokens that were found less often", "N");
+    opts.optflag("", OPT_SPILL, "Spill token counts to temporary files to save memory");
+    opts.optopt("", OPT_NGRAMS, "Count sequences of N tokens, implies --all-words", "N");
+    opts.optopt(
+        "",
+        OPT_PHRASES_FROM,
+        "Only count n-grams made of words from this file, implies --all-words",
+        "FILE",
+    );
+    opts.optopt("", OPT_INPUT_FORMAT, "Format of the article", "text|mediawiki|html|markdown");
+    opts.optflag("", OPT_STRIP_MARKUP, "Remove wiki markup from the pages of a MediaWiki dump");
+    opts.optopt("", OPT_JSONL_FIELD, "Count only this field of each JSON Lines record", "PATH");
+    opts.optopt("", OPT_CSV_COLUMN, "Count only this column of each CSV record", "NAME");
+    opts.optopt("", OPT_GROUP_BY, "Also print the counts per value of this field", "FIELD");
+    opts.optopt("", OPT_RANGE, "Count only the lines starting in this byte range", "START:END");
+    opts.optopt("", OPT_PARTIAL, "Write the counts to a partial-count file for merging", "FILE");
+    opts.optopt("", OPT_CHECKPOINT, "Save the counts so far from time to time", "FILE");
+    opts.optopt("", OPT_CHECKPOINT_INTERVAL, "Seconds between checkpoints, 60 by default", "SECS");
+    opts.optopt("", OPT_RESUME, "Continue counting after the counts saved in a checkpoint", "FILE");
+    opts.optflag("", OPT_PROGRESS, "Show the progress on stderr if it is a terminal");
+    opts.optopt("", OPT_STATUS_INTERVAL, "Print a status line on stderr every SECS", "SECS");
+    opts.optflag("", OPT_STATS, "Print statistics about the automata and the scan on stderr");
+    opts.optopt("", OPT_STATS_FORMAT, "Format of the statistics", "text|json");
+    opts.optflag("", OPT_DOT, "With explain, print the NFA as Graphviz DOT, the default");
+    opts.optflag("", OPT_DFA, "With explain, print the DFA transition table instead");
+    opts.optopt(
+        "j",
+        OPT_THREADS,
+        "Number of threads, 1 scans on the main thread only, one per core by default",
+        "N",
+    );
+    opts.optopt("", OPT_CHUNK_SIZE, "Bytes of article per chunk, 4MiB by default", "SIZE");
+    opts.optopt("", OPT_MAX_MEMORY, "Fail instead of using more memory than this", "SIZE");
+    opts.optopt(
+        "",
+        OPT_STOP_AT,
+        "Stop counting each word at N, end the scan once all got there",
+        "N",
+    );
+    opts.optflag("", OPT_ANY, "Print nothing, exit with 0 once any word is found or else 1");
+    opts.optflag("h", OPT_HELP, "Print this help menu");
+    opts.optflag("v", OPT_VERSION, "Show the program version");
+
+    opts
+}
+
+/// Size of the chunks to scan in parallel.
+fn chunk_size(matches: &Matches) -> Result<u64, InvalidSize> {
+    matches.opt_str(OPT_CHUNK_SIZE).map_or(Ok(chunks::CHUNK_SIZE), |s| size::parse(&s))
+}
+
+/// When to end the scan early, if at all.
+fn early_stop(matches: &Matches) -> Result<Option<EarlyStop>, ParseIntError> {
+    if matches.opt_present(OPT_ANY) {
+        return Ok(Some(EarlyStop::Any));
+    }
+    Ok(matches.opt_get(OPT_STOP_AT)?.map(EarlyStop::At))
+}
+
+/// Set up the memory budget, if limited, taking the memory of the words file from it. The words
+/// are borrowed from the file, so its size is what they take.
+fn budget(matches: &Matches, words: usize) -> Result<Option<Budget>, Box<dyn Error>> {
+    let Some(limit) = matches.opt_str(OPT_MAX_MEMORY) else {
+        return Ok(None);
+    };
+
+    let budget = Budget::new(size::parse(&limit)?);
+    budget.take("the words file", words as u64)?;
+    Ok(Some(budget))
+}
+
+/// Count every token or n-gram of the article instead of the words of a words file.
+fn count_all_words(matches: &Matches, folding: Folding) -> Result<(), Box<dyn Error>> {
+    let budget = budget(matches, 0)?;
+    let found = AtomicU64::default();
+    let settings = vocabulary::Settings {
+        folding,
+        tokenizer: matches
+            .opt_str(OPT_SEPARATORS)
+            .map_or(Tokenizer::Words, |s| Tokenizer::Separators(s.chars().collect())),
+        min_count: matches.opt_get_default(OPT_MIN_COUNT, 1)?,
+        spill: matches.opt_present(OPT_SPILL),
+        ngrams: matches.opt_get_default(OPT_NGRAMS, 1)?,
+        phrases: match matches.opt_str(OPT_PHRASES_FROM) {
+            Some(path) => Some(read_phrase_words(&path, matches, folding)?),
+            None => None,
+        },
+        chunk_size: chunk_size(matches)?,
+        budget: budget.as_ref(),
+        found: Some(&found),
+    };
+
+    let mut input = input(matches, None, None)?;
+    let progress = progress(matches, &mut input, &matches.free[0])?;
+    let article = input.open(&matches.free[0])?;
+    let found = || found.load(Ordering::Relaxed);
+    progress::watch(progress.as_deref(), found, || vocabulary::process(article, &settings))
+}
+
+/// Read the words that n-grams may consist of. Only literal words are taken into account.
+fn read_phrase_words(
+    path: &str,
+    matches: &Matches,
+    folding: Folding,
+) -> Result<HashSet<String>, Box<dyn Error>> {
+    let format = match matches.opt_get(OPT_WORDS_FORMAT)? {
+        Some(format) => format,
+        None => Format::from_path(path),
+    };
+
+    let words = String::from_utf8(fs::read(path)?)?;
+    let words = words::parse(&words, format)?;
+
+    Ok(words
+        .words()
+        .iter()
+        .filter(|w| w.kind == Kind::Literal && !w.text.is_empty())
+        .map(|w| folding.fold(&w.text))
+        .collect())
+}
+
+/// Print the count of every entry together with its details, followed by the counts summed up for
+/// each group, if the words file has any. Words without an id are identified by their position in
+/// the words file. Additional counts like approximate matches follow in further columns.
+fn print_groups(
+    words: &WordList<'_>,
+    entries: &[&Entry],
+    words_map: &IndexMap<&Word<'_>, AtomicU64>,
+    report: &Report,
+) {
+    let header = header(report);
+
+    println!("id\tlabel\tgroup\tcount{header}");
+    for e in entries {
+        let w = &words.words()[e.word];
+        let count = words_map[e.word].load(Ordering::Relaxed);
+        let group = w.group.as_deref().unwrap_or_default();
+        let columns = columns(report, e.word);
+
+        match &w.id {
+            Some(id) => println!("{id}\t{w}\t{group}\t{count}{columns}"),
+            None => println!("{}\t{w}\t{group}\t{count}{columns}", e.pos),
+        }
+    }
+
+    // Sum up over the distinct words only, so duplicates aren't counted twice. Unit counts can't
+    // be summed up, as several words of a group may be found in the same unit.
+    let mut groups = IndexMap::<_, (u64, u64)>::new();
+    for (i, (w, count)) in words_map.iter().enumerate() {
+        if let Some(group) = &w.group {
+            let sum = groups.entry(group).or_default();
+            sum.0 += count.load(Ordering::Relaxed);
+            sum.1 += report.fuzzy.get(i).copied().unwrap_or_default();
+        }
+    }
+
+    if groups.is_empty() {
+        eprintln!("warning: the words file has no groups, so there are no counts per group");
+        return;
+    }
+
+    println!();
+    println!("group\tcount{}", if report.fuzzy.is_empty() { "" } else { "\tfuzzy" });
+    for (group, (count, fuzzy)) in groups {
+        if report.fuzzy.is_empty() {
+            println!("{group}\t{count}");
+        } else {
+            println!("{group}\t{count}\t{fuzzy}");
+        }
+    }
+}
+
+/// Names of the additional columns of counts that are enabled, each preceded by a tab.
+fn header(report: &Report) -> String {
+    let mut header = String::new();
+    if !report.fuzzy.is_empty() {
+        header.push_str("\tfuzzy");
+    }
+    if report.units.is_some() {
+        header.push_str("\tunits");
+    }
+    header
+}
+
+/// Additional counts of a word that are enabled, each preceded by a tab.
+fn columns(report: &Report, word: usize) -> String {
+    let mut columns = String::new();
+    if let Some(fuzzy) = report.fuzzy.get(word) {
+        write!(columns, "\t{fuzzy}").unwrap();
+    }
+    if let Some(units) = &report.units {
+        write!(columns, "\t{}", units.frequency[word]).unwrap();
+    }
+    columns
+}
+
+/// Print the count of every word, followed by everything else that was asked for.
+fn print_counts(
+    matches: &Matches,
+    words: &WordList<'_>,
+    words_map: &IndexMap<&Word<'_>, AtomicU64>,
+    report: &Report,
+    unit: Option<&Unit>,
+    queries: &[Query],
+) -> Result<(), Box<dyn Error>> {
+    let entries: Vec<_> = if matches.opt_present(OPT_DEDUPE) {
+        words.distinct_entries().collect()
     } else {
-        ahocorasick::process(&words_map, article);
+        words.entries().iter().collect()
+    };
+
+    if matches.opt_present(OPT_GROUPS) {
+        print_groups(words, &entries, words_map, report);
+    } else {
+        for e in entries {
+            println!("{:?}{}", words_map[e.word], columns(report, e.word));
+        }
+    }
+
+    if matches.opt_present(OPT_EXPAND) {
+        print_expansions(words, report);
+    }
+
+    if let (Some(unit), Some(units)) = (unit, &report.units) {
+        if !queries.is_empty() {
+            print_queries(queries, unit, units);
+        }
+
+        if let Some(path) = matches.opt_str(OPT_TERM_MATRIX) {
+            units::write_matrix(units, &word_ids(words), fs::File::create(path)?)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// A hash of the words file and of the options that decide what is counted in the article, to
+/// tell whether partial counts belong together.
+fn words_hash(words_file: &str, format: Format, folding: Folding, input: &Input) -> String {
+    partial::hash(&[
+        words_file.as_bytes(),
+        format!("{format:?}").as_bytes(),
+        format!("{folding:?}").as_bytes(),
+        format!("{:?}", input.format).as_bytes(),
+        &[u8::from(input.strip)],
+        format!("{:?}", input.field).as_bytes(),
+    ])
+}
+
+/// Continue counting after the checkpoint given with `--resume`, whose counts are added right away,
+/// and prepare the checkpoints to write with `--checkpoint`. The input is left to read only the
+/// part of the article that isn't counted yet, plus the lead-in before it, whose length is
+/// returned as well.
+fn resume(
+    matches: &Matches,
+    input: &mut Input,
+    words_hash: &str,
+    words: &WordList<'_>,
+    words_map: &IndexMap<&Word<'_>, AtomicU64>,
+    folding: Folding,
+) -> Result<(Option<Checkpoint>, usize), Box<dyn Error>> {
+    let resumed = match matches.opt_str(OPT_RESUME) {
+        Some(path) => Some(Partial::read(BufReader::new(fs::File::open(path)?))?),
+        None => None,
+    };
+    let checkpoint = matches.opt_str(OPT_CHECKPOINT);
+    if resumed.is_none() && checkpoint.is_none() {
+        return Ok((None, 0));
+    }
+
+    for option in [
+        OPT_NAIVE,
+        OPT_FOURTYTWO,
+        OPT_POSITIONS,
+        OPT_EXPAND,
+        OPT_MAX_EDITS,
+        OPT_COOCCURRENCE,
+        OPT_QUERY,
+        OPT_UNIT,
+        OPT_DOC_SEPARATOR,
+        OPT_TERM_MATRIX,
+        OPT_INPUT_FORMAT,
+        OPT_JSONL_FIELD,
+        OPT_CSV_COLUMN,
+        OPT_GROUP_BY,
+        OPT_STOP_AT,
+        OPT_ANY,
+    ] {
+        if matches.opt_present(option) {
+            return Err(ResumeError::Unsupported(option).into());
+        }
+    }
+
+    let (start, end) = match input.range {
+        Some(range) => range,
+        None => (0, fs::metadata(&matches.free[1])?.len()),
+    };
+    let done = match resumed {
+        Some(resumed) => {
+            if resumed.words != words_hash {
+                return Err(MergeError::Words(words_hash.to_owned(), resumed.words).into());
+            }
+            let &[(first, _)] = resumed.ranges.as_slice() else {
+                return Err(ResumeError::Ranges.into());
+            };
+            if first != start {
+                return Err(ResumeError::Start(first, start).into());
+            }
+
+            for (count, &resumed) in words_map.values().zip(&resumed.counts) {
+                count.fetch_add(resumed, Ordering::SeqCst);
+            }
+            resumed
+        }
+        None => Partial {
+            words: words_hash.to_owned(),
+            ranges: vec![(start, start)],
+            entries: words.entries().iter().map(|e| e.word).collect(),
+            counts: vec![0; words_map.len()],
+        },
+    };
+
+    // Matches right after the counted part may depend on the text before it, like whole words.
+    let counted = done.ranges[0].1;
+    let before = usize::try_from(counted - start).unwrap_or(usize::MAX);
+    let lead_in = ahocorasick::lead_in(words_map, folding).min(before);
+    let base = counted - lead_in as u64;
+    input.range = Some((base, end));
+
+    let checkpoint = match checkpoint {
+        Some(path) => {
+            let interval = matches.opt_get_default(OPT_CHECKPOINT_INTERVAL, 60)?;
+            Some(Checkpoint::new(path, Duration::from_secs(interval), done, base))
+        }
+        None => None,
+    };
+
+    Ok((checkpoint, lead_in))
+}
+
+/// Write the counts of a byte range of the article to a partial-count file.
+fn write_partial(
+    path: &str,
+    words_hash: String,
+    range: Option<(u64, u64)>,
+    article: &str,
+    words: &WordList<'_>,
+    words_map: &IndexMap<&Word<'_>, AtomicU64>,
+) -> Result<(), Box<dyn Error>> {
+    let range = match range {
+        Some(range) => range,
+        None => (0, fs::metadata(article)?.len()),
+    };
+    let partial = Partial {
+        words: words_hash,
+        ranges: vec![range],
+        entries: words.entries().iter().map(|e| e.word).collect(),
+        counts: words_map.values().map(|c| c.load(Ordering::SeqCst)).collect(),
+    };
+    partial.write(BufWriter::new(fs::File::create(path)?))?;
+    Ok(())
+}
+
+/// Print the automata built for the literal words of the words file given after the explain
+/// command.
+fn explain(matches: &Matches) -> Result<(), Box<dyn Error>> {
+    let normalization = matches.opt_get::<Normalization>(OPT_NORMALIZE)?;
+    let folding = Folding::new(matches.opt_present(OPT_UNICODE_FOLD), normalization);
+    let format = match matches.opt_get(OPT_WORDS_FORMAT)? {
+        Some(format) => format,
+        None => Format::from_path(&matches.free[1]),
+    };
+
+    let words = fs::read_to_string(&matches.free[1])?;
+    let words = words::parse(&words, format)?;
+    let words_map: IndexMap<_, _> =
+        words.words().iter().map(|w| (w, AtomicU64::default())).collect();
+
+    let dfa = matches.opt_present(OPT_DFA);
+    ahocorasick::explain(&words_map, folding, dfa, io::stdout().lock())?;
+    Ok(())
+}
+
+/// Add up the partial-count files given after the merge command. The sum is printed like the counts
+/// of a single run, or written to another partial-count file.
+fn merge(matches: &Matches) -> Result<(), Box<dyn Error>> {
+    let parts = matches.free[1..]
+        .iter()
+        .map(|path| Ok(Partial::read(BufReader::new(fs::File::open(path)?))?))
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+    let (merged, gaps) = Partial::merge(parts)?;
+
+    for (start, end) in gaps {
+        eprintln!(
+            "warning: bytes {start}:{end} of the article are missing from the partial counts"
+        );
+    }
+
+    if let Some(path) = matches.opt_str(OPT_PARTIAL) {
+        merged.write(BufWriter::new(fs::File::create(path)?))?;
+        return Ok(());
     }
 
-    // Printing out our findings.
-    for w in words {
-        println!("{:?}", words_map[w]);
+    let mut seen = vec![false; merged.counts.len()];
+    for &word in &merged.entries {
+        if matches.opt_present(OPT_DEDUPE) && std::mem::replace(&mut seen[word], true) {
+            continue;
+        }
+        println!("{}", merged.counts[word]);
     }
 
     Ok(())
 }
 
+/// How to read the article, converting it into plain text if it has another format. Positions
+/// within the converted text are mapped back to the original article with `offsets` and counts per
+/// group of records are collected in `groups`, if given.
+fn input(
+    matches: &Matches,
+    offsets: Option<Arc<Offsets>>,
+    groups: Option<Arc<Groups>>,
+) -> Result<Input, Box<dyn Error>> {
+    let field = match (matches.opt_str(OPT_JSONL_FIELD), matches.opt_str(OPT_CSV_COLUMN)) {
+        (Some(_), Some(_)) => return Err(InvalidFields::Both.into()),
+        (Some(path), None) => Some(Field::Json(path)),
+        (None, Some(column)) => Some(Field::Csv(column)),
+        (None, None) => None,
+    };
+    let format = matches.opt_get(OPT_INPUT_FORMAT)?.unwrap_or_default();
+    let group_by = matches.opt_str(OPT_GROUP_BY);
+    // Markup elements may span lines, so only plain text and records can be split at any line.
+    if matches.opt_present(OPT_RANGE) && field.is_none() && format != InputFormat::Text {
+        return Err(InvalidRange::Markup.into());
+    }
+    if group_by.is_some() {
+        if field.is_none() {
+            return Err(InvalidFields::GroupWithoutField.into());
+        }
+        if format != InputFormat::Text {
+            return Err(InvalidFields::GroupWithFormat.into());
+        }
+    }
+
+    Ok(Input {
+        format,
+        strip: matches.opt_present(OPT_STRIP_MARKUP),
+        field,
+        group_by,
+        offsets,
+        groups,
+        range: None,
+        progress: None,
+    })
+}
+
+/// The queries to count the units satisfying them for.
+fn queries(matches: &Matches, words: &WordList<'_>) -> Result<Vec<Query>, QueryError> {
+    matches.opt_strs(OPT_QUERY).iter().map(|q| Query::parse(q, words)).collect()
+}
+
+/// How to split the article into units, if counting per unit is enabled explicitly or needed by
+/// queries. A document separator implies counting per document.
+fn unit(matches: &Matches, queries: bool) -> Result<Option<Unit>, Box<dyn Error>> {
+    let format: InputFormat = matches.opt_get(OPT_INPUT_FORMAT)?.unwrap_or_default();
+    let separator = match matches.opt_get::<Regex>(OPT_DOC_SEPARATOR)? {
+        Some(separator) => Some(separator),
+        None => format.separator(),
+    };
+    let kind = match matches.opt_get(OPT_UNIT)? {
+        Some(kind) => kind,
+        None if separator.is_some() => units::Kind::Document,
+        None if queries || matches.opt_present(OPT_TERM_MATRIX) => units::Kind::Line,
+        None => return Ok(None),
+    };
+
+    Ok(Some(Unit::new(kind, separator)?))
+}
+
+/// The id of every distinct word, falling back to the position of its first entry.
+fn word_ids(words: &WordList<'_>) -> Vec<String> {
+    let mut ids: Vec<_> = words.words().iter().map(|w| w.id.clone()).collect();
+    for e in words.distinct_entries() {
+        ids[e.word].get_or_insert_with(|| e.pos.to_string());
+    }
+
+    ids.into_iter().map(Option::unwrap_or_default).collect()
+}
+
+/// Print the count of each word within the records of each group.
+fn print_record_groups(field: &str, groups: &[(String, Vec<(usize, u64)>)], ids: &[String]) {
+    println!();
+    println!("{field}\tword\tcount");
+
+    for (name, row) in groups {
+        for &(id, count) in row {
+            println!("{name}\t{}\t{count}", ids[id]);
+        }
+    }
+}
+
+/// Print the strings that matched each wildcard, starting with the most frequent ones.
+fn print_expansions(words: &WordList<'_>, report: &Report) {
+    println!();
+    println!("wildcard\tmatch\tcount");
+
+    for (w, expansion) in words.words().iter().zip(&report.expansions) {
+        if w.kind != Kind::Glob {
+            continue;
+        }
+
+        let mut expansion: Vec<_> = expansion.iter().collect();
+        expansion.sort_unstable_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+
+        for (s, count) in expansion {
+            println!("{w}\t{}\t{count}", s.escape_debug());
+        }
+    }
+}
+
+/// Print how many units satisfy each query.
+fn print_queries(queries: &[Query], unit: &Unit, report: &UnitReport) {
+    println!();
+    println!("query\t{}", unit.plural());
+
+    for (query, count) in queries.iter().zip(&report.queries) {
+        println!("{query}\t{count}");
+    }
+}
+
+/// Print all words that are listed more than once, as well as empty words.
+fn report_duplicates(words: &WordList<'_>) {
+    let unit = words.format().unit();
+    let join = |pos: &[usize]| pos.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ");
+
+    for (w, pos) in words.duplicates() {
+        eprintln!("duplicate `{w}` ({unit} {})", join(&pos));
+    }
+
+    let empty = words.empty();
+    if !empty.is_empty() {
+        eprintln!("empty word, never matches ({unit} {})", join(&empty));
+    }
+
+    eprintln!("{} distinct words in {} entries", words.words().len(), words.entries().len());
+}
+
 /// Print out instructions about how to use this program.
 fn print_usage(opts: &Options) {
-    let brief = format!("Usage: {} WORDS_FILE ARTICLE_FILE [options]", env!("CARGO_PKG_NAME"));
+    let name = env!("CARGO_PKG_NAME");
+    let brief = format!(
+        "Usage: {name} WORDS_FILE ARTICLE_FILE [options]\n       {name} --all-words ARTICLE_FILE \
+         [options]\n       {name} {CMD_MERGE} PARTIAL_FILE... [options]\n       {name} \
+         {CMD_EXPLAIN} WORDS_FILE [--dot|--dfa] [options]"
+    );
     print!("{}", opts.usage(&brief));
 }
 
@@ -0,0 +1,87 @@
+//! Keeping a run within the memory limit given by `--max-memory`.
+//!
+//! The big allocations are the words, their automata and the chunks of the article being scanned.
+//! Each of them takes its share of the budget before it is allocated, so a run that doesn't fit
+//! fails right away with an error naming the culprit, instead of being killed halfway through.
+//! Smaller allocations, like the counts or the tables of units, aren't tracked, so the limit should
+//! leave some room below the actual limit of the machine or container.
+
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::aho_corasick::AhoCorasick;
+
+/// The memory that is left to use.
+#[derive(Debug)]
+pub struct Budget {
+    limit: u64,
+    used: AtomicU64,
+}
+
+impl Budget {
+    pub const fn new(limit: u64) -> Self {
+        Self { limit, used: AtomicU64::new(0) }
+    }
+
+    /// Bytes that are left.
+    pub fn left(&self) -> u64 {
+        self.limit.saturating_sub(self.used.load(Ordering::SeqCst))
+    }
+
+    /// Take `bytes` for `what` from the budget, if they are left.
+    pub fn take(&self, what: &'static str, bytes: u64) -> Result<(), OutOfMemory> {
+        self.used
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                used.checked_add(bytes).filter(|&used| used <= self.limit)
+            })
+            .map(|_| ())
+            .map_err(|_| OutOfMemory { what, needed: bytes, left: self.left(), limit: self.limit })
+    }
+}
+
+/// Build an automaton for the patterns. Within a budget, it may take at most half of what is left,
+/// so there is still room for the chunks of the article, and is searched with the NFA if the DFA
+/// doesn't fit.
+pub fn automaton<P: AsRef<[u8]>>(
+    patterns: &[P],
+    budget: Option<&Budget>,
+) -> Result<AhoCorasick, OutOfMemory> {
+    let Some(budget) = budget else {
+        return Ok(AhoCorasick::new(patterns));
+    };
+
+    let limit = usize::try_from(budget.left() / 2).unwrap_or(usize::MAX);
+    let ac = AhoCorasick::with_limit(patterns, limit);
+    budget.take("the automaton of the words", ac.heap_bytes() as u64)?;
+    Ok(ac)
+}
+
+/// How many of the `most` buffers for chunks fit into the budget, taking their memory from it.
+/// Each chunk needs `bytes` while it is scanned.
+pub fn buffers(budget: &Budget, most: usize, bytes: u64) -> Result<usize, OutOfMemory> {
+    let buffers = (budget.left() / bytes).clamp(1, most as u64);
+    budget.take("scanning the chunks of the article (see --chunk-size)", buffers * bytes)?;
+    Ok(usize::try_from(buffers).unwrap_or(usize::MAX))
+}
+
+/// Error returned when something doesn't fit into the memory limit.
+#[derive(Debug)]
+pub struct OutOfMemory {
+    what: &'static str,
+    needed: u64,
+    left: u64,
+    limit: u64,
+}
+
+impl fmt::Display for OutOfMemory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} needs {} bytes, but only {} bytes of the memory limit of {} bytes are left",
+            self.what, self.needed, self.left, self.limit
+        )
+    }
+}
+
+impl Error for OutOfMemory {}
@@ -4,23 +4,83 @@ use std::io::{BufRead, BufReader, Read};
 use std::sync::atomic::{AtomicU64, Ordering};
 
 use indexmap::map::IndexMap;
-use rayon::prelude::*;
+use regex::{Regex, RegexBuilder};
+
+use crate::fold::Folding;
+use crate::threads;
+use crate::words::{self, Kind, Word};
+
+/// A word prepared for searching.
+enum Pattern {
+    Literal(String),
+    Regex(Regex),
+}
 
 #[allow(dead_code, unused_variables)]
-pub fn process<R>(words: &IndexMap<&str, AtomicU64>, article: BufReader<R>)
+pub fn process<R>(
+    words: &IndexMap<&Word<'_>, AtomicU64>,
+    article: BufReader<R>,
+    folding: Folding,
+) -> Result<(), regex::Error>
 where
     R: Read + Send,
 {
-    let patterns: Vec<_> = words.keys().collect();
+    let case_folding = folding.with_case();
+    let patterns = words
+        .keys()
+        .map(|w| {
+            let pattern = match w.kind {
+                Kind::Literal if w.flags.ignore_case => {
+                    Pattern::Literal(case_folding.fold(&w.text))
+                }
+                Kind::Literal => Pattern::Literal(folding.fold(&w.text)),
+                Kind::Regex | Kind::Glob => Pattern::Regex(
+                    RegexBuilder::new(&w.regex().unwrap())
+                        .case_insensitive(w.flags.ignore_case || folding.is_case_folded())
+                        .build()?,
+                ),
+            };
+            Ok((pattern, w.flags))
+        })
+        .collect::<Result<Vec<_>, regex::Error>>()?;
+    let ignore_case = words.keys().any(|w| w.kind == Kind::Literal && w.flags.ignore_case);
 
-    article.lines().par_bridge().filter_map(Result::ok).for_each(|line| {
-        for p in &patterns {
-            let mut r = &line[..];
+    threads::for_each(article.lines().map_while(Result::ok), |line| {
+        let plain = folding.fold_mapped(&line);
+        let folded = ignore_case.then(|| case_folding.fold_mapped(&line));
 
-            while let Some(i) = r.find(*p) {
-                words[*p].fetch_add(1, Ordering::SeqCst);
-                r = &r[i + 1..];
+        for (i, (p, flags)) in patterns.iter().enumerate() {
+            let text = match (&folded, p) {
+                (Some(folded), Pattern::Literal(_)) if flags.ignore_case => folded.text(),
+                _ => plain.text(),
+            };
+            let count = |start, end| {
+                if !flags.whole_word || words::is_whole_word(text, start, end) {
+                    words[i].fetch_add(1, Ordering::SeqCst);
+                }
+            };
+
+            match p {
+                // Empty words would match at every single position, so they're skipped.
+                Pattern::Literal(p) if p.is_empty() => {}
+                Pattern::Literal(p) => {
+                    let mut start = 0;
+
+                    while let Some(pos) = text[start..].find(p.as_str()) {
+                        let pos = start + pos;
+                        count(pos, pos + p.len());
+                        // Skip a whole character to stay on a valid UTF-8 boundary.
+                        start = pos + text[pos..].chars().next().map_or(1, char::len_utf8);
+                    }
+                }
+                Pattern::Regex(re) => {
+                    for mat in re.find_iter(text).filter(|m| !m.is_empty()) {
+                        count(mat.start(), mat.end());
+                    }
+                }
             }
         }
     });
+
+    Ok(())
 }
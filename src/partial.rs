@@ -0,0 +1,107 @@
+//! Partial counts of a part of the article, to be merged with the counts of the other parts.
+//!
+//! Each partial-count file carries a hash of the words file and the options that affect matching
+//! or the text read from the article, so only counts of the same words in the same text are ever
+//! added up, as well as the byte ranges it covers, so parts counted twice are noticed.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+/// Counts of the words within some byte ranges of the article.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Partial {
+    /// Hash of the words file and matching options, as hexadecimal string.
+    pub words: String,
+    /// The byte ranges of the article that were counted.
+    pub ranges: Vec<(u64, u64)>,
+    /// For each entry of the words file, the index of its distinct word.
+    pub entries: Vec<usize>,
+    /// The count of each distinct word.
+    pub counts: Vec<u64>,
+}
+
+impl Partial {
+    pub fn read(input: impl io::Read) -> Result<Self, serde_json::Error> {
+        serde_json::from_reader(input)
+    }
+
+    pub fn write(&self, out: impl io::Write) -> Result<(), serde_json::Error> {
+        serde_json::to_writer(out, self)
+    }
+
+    /// Add up the counts of all parts. Ranges that lie between the parts are returned as gaps.
+    pub fn merge(parts: Vec<Self>) -> Result<(Self, Vec<(u64, u64)>), MergeError> {
+        let mut parts = parts.into_iter();
+        let mut merged = parts.next().ok_or(MergeError::Empty)?;
+
+        for part in parts {
+            if part.words != merged.words || part.entries != merged.entries {
+                return Err(MergeError::Words(merged.words, part.words));
+            }
+
+            for (total, count) in merged.counts.iter_mut().zip(part.counts) {
+                *total += count;
+            }
+            merged.ranges.extend(part.ranges);
+        }
+
+        merged.ranges.sort_unstable();
+        let mut gaps = vec![];
+        for pair in merged.ranges.windows(2) {
+            let ((_, end), (start, _)) = (pair[0], pair[1]);
+            if start < end {
+                return Err(MergeError::Overlap(pair[0], pair[1]));
+            }
+            if start > end {
+                gaps.push((end, start));
+            }
+        }
+
+        Ok((merged, gaps))
+    }
+}
+
+/// A stable hash of the words file and everything else that decides what matches, so partial
+/// counts of different runs can be compared.
+pub fn hash(parts: &[&[u8]]) -> String {
+    // 64-bit FNV-1a, which doesn't change between builds like the hasher of the standard library.
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for part in parts {
+        for &b in (part.len() as u64).to_le_bytes().iter().chain(*part) {
+            hash ^= u64::from(b);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    format!("{hash:016x}")
+}
+
+/// Error returned when partial counts can't be merged.
+#[derive(Debug)]
+pub enum MergeError {
+    Empty,
+    Words(String, String),
+    Overlap((u64, u64), (u64, u64)),
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => f.write_str("no partial counts to merge"),
+            Self::Words(a, b) => write!(
+                f,
+                "partial counts come from different words files or options (hash {a} and {b})"
+            ),
+            Self::Overlap(first, second) => write!(
+                f,
+                "the ranges {}:{} and {}:{} overlap, so they'd be counted twice",
+                first.0, first.1, second.0, second.1
+            ),
+        }
+    }
+}
+
+impl Error for MergeError {}
@@ -0,0 +1,188 @@
+//! Reporting the progress of a scan on stderr.
+//!
+//! The bytes are counted while the article is read, before it is split into chunks, so the workers
+//! don't do any extra work. Matches are taken from the counts of the words whenever the progress is
+//! reported, which is a lot less often than they change.
+
+use std::fmt;
+use std::io::{self, IsTerminal, Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the progress is redrawn on a terminal.
+const REDRAW: Duration = Duration::from_millis(250);
+
+const MIB: f64 = 1024.0 * 1024.0;
+
+/// Shared progress of a scan.
+#[derive(Debug)]
+pub struct Progress {
+    /// Number of bytes to read, if known.
+    total: Option<u64>,
+    /// Number of bytes read so far.
+    bytes: AtomicU64,
+    /// Redraw a single line on the terminal.
+    terminal: bool,
+    /// Print a status line for logs this often.
+    status: Option<Duration>,
+}
+
+impl Progress {
+    /// Create the progress of reading `total` bytes, drawn on the terminal if asked to and stderr
+    /// is one, and printed as status lines every `status`. Returns `None` if nothing is reported.
+    pub fn new(total: Option<u64>, terminal: bool, status: Option<Duration>) -> Option<Self> {
+        let terminal = terminal && io::stderr().is_terminal();
+        (terminal || status.is_some()).then(|| Self {
+            total,
+            bytes: AtomicU64::default(),
+            terminal,
+            status,
+        })
+    }
+
+    /// Count the bytes read from a reader.
+    pub fn reader<R: Read>(self: &Arc<Self>, inner: R) -> Counted<R> {
+        Counted { inner, progress: Arc::clone(self) }
+    }
+
+    /// Report the progress until the sender of `done` is dropped.
+    fn report(&self, done: &Receiver<()>, matches: &impl Fn() -> u64) {
+        let start = Instant::now();
+        let tick =
+            if self.terminal { REDRAW } else { self.status.map_or(REDRAW, |s| s.max(REDRAW)) };
+        let mut logged = start;
+        let mut stderr = io::stderr();
+
+        loop {
+            let finished = match done.recv_timeout(tick) {
+                Err(RecvTimeoutError::Timeout) => false,
+                Ok(()) | Err(RecvTimeoutError::Disconnected) => true,
+            };
+            let status = Status::new(self, start.elapsed(), matches());
+
+            // Errors writing to stderr can't be reported anywhere, so they are ignored.
+            if self.status.is_some_and(|interval| finished || logged.elapsed() >= interval) {
+                logged = Instant::now();
+                let clear = if self.terminal { "\r\x1b[K" } else { "" };
+                let _ = writeln!(stderr, "{clear}{}", status.line());
+            }
+            if self.terminal {
+                let end = if finished { "\n" } else { "" };
+                let _ = write!(stderr, "\r\x1b[K{status}{end}");
+            }
+
+            if finished {
+                return;
+            }
+        }
+    }
+}
+
+/// Run `scan` while reporting the progress, if any, in the background. `matches` returns the
+/// number of matches found so far.
+pub fn watch<T>(
+    progress: Option<&Progress>,
+    matches: impl Fn() -> u64 + Sync,
+    scan: impl FnOnce() -> T,
+) -> T {
+    let Some(progress) = progress else {
+        return scan();
+    };
+
+    let (done, finished) = mpsc::channel();
+    let matches = &matches;
+    thread::scope(|s| {
+        s.spawn(move || progress.report(&finished, matches));
+
+        // Dropping the sender stops the report, also if the scan panics.
+        let result = scan();
+        drop(done);
+        result
+    })
+}
+
+/// A reader that counts the bytes read from it.
+#[derive(Debug)]
+pub struct Counted<R> {
+    inner: R,
+    progress: Arc<Progress>,
+}
+
+impl<R: Read> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        // Only the total matters, so the counter doesn't order any other memory accesses.
+        self.progress.bytes.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+/// A snapshot of the progress.
+struct Status {
+    bytes: u64,
+    total: Option<u64>,
+    elapsed: Duration,
+    matches: u64,
+}
+
+impl Status {
+    fn new(progress: &Progress, elapsed: Duration, matches: u64) -> Self {
+        let bytes = progress.bytes.load(Ordering::Relaxed);
+        Self { bytes, total: progress.total, elapsed, matches }
+    }
+
+    /// Bytes per second.
+    #[allow(clippy::cast_precision_loss)]
+    fn rate(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.bytes as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// Estimated time left, if the total is known and anything was read yet.
+    #[allow(clippy::cast_precision_loss)]
+    fn eta(&self) -> Option<Duration> {
+        let left = self.total?.saturating_sub(self.bytes);
+        let rate = self.rate();
+        (rate > 0.0).then(|| Duration::from_secs_f64(left as f64 / rate))
+    }
+
+    /// A line of `key=value` pairs for logs, with `-` for unknown values.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn line(&self) -> String {
+        let unknown = || "-".to_owned();
+        format!(
+            "progress bytes={} total={} elapsed={:.1} rate={} matches={} eta={}",
+            self.bytes,
+            self.total.map_or_else(unknown, |total| total.to_string()),
+            self.elapsed.as_secs_f64(),
+            self.rate() as u64,
+            self.matches,
+            self.eta().map_or_else(unknown, |eta| eta.as_secs().to_string()),
+        )
+    }
+}
+
+/// A line for people to read on a terminal.
+impl fmt::Display for Status {
+    #[allow(clippy::cast_precision_loss)]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.1} MiB", self.bytes as f64 / MIB)?;
+        if let Some(total) = self.total.filter(|&total| total > 0) {
+            let percent = self.bytes as f64 / total as f64 * 100.0;
+            write!(f, " of {:.1} MiB ({percent:.1}%)", total as f64 / MIB)?;
+        }
+        write!(f, ", {:.1} MiB/s, {} matches", self.rate() / MIB, self.matches)?;
+        if let Some(eta) = self.eta() {
+            let secs = eta.as_secs();
+            write!(f, ", ETA {}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)?;
+        }
+        Ok(())
+    }
+}
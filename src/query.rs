@@ -0,0 +1,340 @@
+//! Boolean and proximity queries over the words found in each line, paragraph or document.
+//!
+//! A query combines words of the words file with `AND`, `OR` and `NOT`, grouped by parentheses.
+//! `a NEAR/5 b` requires both words to be found at most five tokens apart. Words are referred to by
+//! their id, label or text, and quoted if they contain spaces, parentheses or quotes:
+//!
+//! ```txt
+//! cat NEAR/5 dog AND NOT (bird OR "it doesn't have.")
+//! ```
+//!
+//! `NOT` binds tightest, followed by `NEAR`, `AND` and `OR`.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::units::Found;
+use crate::words::WordList;
+
+/// A parsed query, together with its original text.
+#[derive(Debug)]
+pub struct Query {
+    text: String,
+    expr: Expr,
+}
+
+#[derive(Debug)]
+enum Expr {
+    /// Any of the distinct words with these indices.
+    Word(Vec<usize>),
+    Near(Vec<usize>, Vec<usize>, u64),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+/// Error returned when parsing an invalid query.
+#[derive(Debug)]
+pub struct QueryError {
+    query: String,
+    message: String,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "query `{}`: {}", self.query, self.message)
+    }
+}
+
+impl Error for QueryError {}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Token {
+    Term(String),
+    And,
+    Or,
+    Not,
+    Near(u64),
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Term(term) => write!(f, "`{term}`"),
+            Self::And => f.write_str("`AND`"),
+            Self::Or => f.write_str("`OR`"),
+            Self::Not => f.write_str("`NOT`"),
+            Self::Near(n) => write!(f, "`NEAR/{n}`"),
+            Self::Open => f.write_str("`(`"),
+            Self::Close => f.write_str("`)`"),
+        }
+    }
+}
+
+impl Query {
+    /// Parse a query, resolving its terms to the words of the list.
+    pub fn parse(text: &str, words: &WordList<'_>) -> Result<Self, QueryError> {
+        let error = |message: String| QueryError { query: text.to_owned(), message };
+
+        let tokens = tokenize(text).map_err(error)?;
+        let mut parser = Parser { tokens: tokens.into_iter().peekable(), words };
+        let expr = parser.or().map_err(error)?;
+
+        match parser.tokens.next() {
+            None => Ok(Self { text: text.to_owned(), expr }),
+            Some(token) => Err(error(format!("unexpected {token}"))),
+        }
+    }
+
+    /// Whether any query needs the token positions of the matches.
+    pub fn needs_positions(queries: &[Self]) -> bool {
+        queries.iter().any(|q| q.expr.has_near())
+    }
+
+    /// Check whether a unit with the given matches satisfies the query.
+    pub fn matches(&self, found: &Found) -> bool {
+        self.expr.eval(found)
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl Expr {
+    fn has_near(&self) -> bool {
+        match self {
+            Self::Word(_) => false,
+            Self::Near(..) => true,
+            Self::Not(e) => e.has_near(),
+            Self::And(a, b) | Self::Or(a, b) => a.has_near() || b.has_near(),
+        }
+    }
+
+    fn eval(&self, found: &Found) -> bool {
+        match self {
+            Self::Word(ids) => ids.iter().any(|&id| found.contains(id)),
+            Self::Near(a, b, n) => {
+                // Each match is identified by its word and index, so it never pairs with itself.
+                let matches = |ids: &[usize]| -> Vec<(usize, usize, u64)> {
+                    ids.iter()
+                        .flat_map(|&id| {
+                            found.positions(id).iter().enumerate().map(move |(i, &p)| (id, i, p))
+                        })
+                        .collect()
+                };
+                let b = matches(b);
+                matches(a).iter().any(|&(ida, ia, pa)| {
+                    b.iter().any(|&(idb, ib, pb)| (ida, ia) != (idb, ib) && pa.abs_diff(pb) <= *n)
+                })
+            }
+            Self::Not(e) => !e.eval(found),
+            Self::And(a, b) => a.eval(found) && b.eval(found),
+            Self::Or(a, b) => a.eval(found) || b.eval(found),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut rest = text.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        let len = match c {
+            '(' => {
+                tokens.push(Token::Open);
+                1
+            }
+            ')' => {
+                tokens.push(Token::Close);
+                1
+            }
+            '"' => {
+                let end = rest[1..].find('"').ok_or("unclosed quote")? + 1;
+                tokens.push(Token::Term(rest[1..end].to_owned()));
+                end + 1
+            }
+            _ => {
+                let len = rest.find(|c: char| c.is_whitespace() || "()\"".contains(c));
+                let word = &rest[..len.unwrap_or(rest.len())];
+
+                tokens.push(match word {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => match word.strip_prefix("NEAR/") {
+                        Some(n) => Token::Near(
+                            n.parse().map_err(|_| format!("invalid distance in `{word}`"))?,
+                        ),
+                        None => Token::Term(word.to_owned()),
+                    },
+                });
+                word.len()
+            }
+        };
+
+        rest = rest[len..].trim_start();
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a, 'w> {
+    tokens: std::iter::Peekable<std::vec::IntoIter<Token>>,
+    words: &'a WordList<'w>,
+}
+
+impl Parser<'_, '_> {
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.tokens.next_if_eq(&Token::Or).is_some() {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.near()?;
+        while self.tokens.next_if_eq(&Token::And).is_some() {
+            expr = Expr::And(Box::new(expr), Box::new(self.near()?));
+        }
+        Ok(expr)
+    }
+
+    fn near(&mut self) -> Result<Expr, String> {
+        let expr = self.not()?;
+        let Some(&Token::Near(n)) = self.tokens.peek() else {
+            return Ok(expr);
+        };
+        self.tokens.next();
+
+        let (Expr::Word(a), Expr::Word(b)) = (expr, self.not()?) else {
+            return Err(format!("`NEAR/{n}` only works between two words"));
+        };
+        Ok(Expr::Near(a, b, n))
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        match self.tokens.next() {
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.not()?))),
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                match self.tokens.next() {
+                    Some(Token::Close) => Ok(expr),
+                    Some(token) => Err(format!("expected `)`, found {token}")),
+                    None => Err("expected `)`, found the end".to_owned()),
+                }
+            }
+            Some(Token::Term(term)) => self.term(&term),
+            Some(token) => Err(format!("expected a word, found {token}")),
+            None => Err("expected a word, found the end".to_owned()),
+        }
+    }
+
+    /// Resolve a term to all words it could refer to.
+    fn term(&self, term: &str) -> Result<Expr, String> {
+        let ids: Vec<_> = self
+            .words
+            .words()
+            .iter()
+            .enumerate()
+            .filter(|(_, w)| {
+                w.id.as_deref() == Some(term)
+                    || w.label.as_deref() == Some(term)
+                    || w.text == term
+                    || w.to_string() == term
+            })
+            .map(|(i, _)| i)
+            .collect();
+
+        if ids.is_empty() {
+            return Err(format!("`{term}` isn't in the words file"));
+        }
+        Ok(Expr::Word(ids))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::words::{self, Format};
+
+    fn parse(text: &str) -> Result<Query, String> {
+        let words = words::parse("a\nb\nc\nd\n", Format::Plain).unwrap();
+        Query::parse(text, &words).map_err(|e| e.message)
+    }
+
+    fn expr(text: &str) -> String {
+        format!("{:?}", parse(text).unwrap().expr)
+    }
+
+    fn eval(text: &str, matches: &[(usize, u64)]) -> bool {
+        parse(text).unwrap().matches(&Found::new(matches))
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(expr("a OR b AND c"), "Or(Word([0]), And(Word([1]), Word([2])))");
+        assert_eq!(expr("a AND b OR c"), "Or(And(Word([0]), Word([1])), Word([2]))");
+        assert_eq!(expr("(a OR b) AND c"), "And(Or(Word([0]), Word([1])), Word([2]))");
+        assert_eq!(expr("a AND b NEAR/2 c"), "And(Word([0]), Near([1], [2], 2))");
+        assert_eq!(expr("a OR b OR c"), "Or(Or(Word([0]), Word([1])), Word([2]))");
+
+        assert!(eval("a OR b AND c", &[(0, 0)]));
+        assert!(!eval("(a OR b) AND c", &[(0, 0)]));
+    }
+
+    #[test]
+    fn not() {
+        assert_eq!(expr("NOT a AND b"), "And(Not(Word([0])), Word([1]))");
+        assert_eq!(expr("NOT (a AND b)"), "Not(And(Word([0]), Word([1])))");
+        assert_eq!(expr("NOT NOT a"), "Not(Not(Word([0])))");
+
+        assert!(eval("NOT a AND b", &[(1, 0)]));
+        assert!(!eval("NOT a AND b", &[(0, 0), (1, 0)]));
+        assert!(eval("NOT (a AND b)", &[(0, 0)]));
+        assert!(eval("NOT d", &[]));
+    }
+
+    #[test]
+    fn near() {
+        let found = [(0, 2), (1, 5), (2, 9), (2, 12)];
+        assert!(eval("a NEAR/3 b", &found));
+        assert!(!eval("a NEAR/2 b", &found));
+        assert!(eval("b NEAR/3 a", &found));
+        assert!(eval("b NEAR/4 c", &found));
+        assert!(!eval("b NEAR/3 c", &found));
+        assert!(!eval("a NEAR/100 d", &found));
+
+        // A match never pairs with itself, only with another match of the same word.
+        assert!(!eval("a NEAR/0 a", &found));
+        assert!(eval("c NEAR/3 c", &found));
+        assert!(!eval("c NEAR/2 c", &found));
+        assert!(eval("a NEAR/0 b", &[(0, 4), (1, 4)]));
+    }
+
+    #[test]
+    fn malformed() {
+        for (query, message) in [
+            ("", "expected a word, found the end"),
+            ("a AND", "expected a word, found the end"),
+            ("AND a", "expected a word, found `AND`"),
+            ("a b", "unexpected `b`"),
+            ("a)", "unexpected `)`"),
+            ("(a OR b", "expected `)`, found the end"),
+            ("(a b)", "expected `)`, found `b`"),
+            ("\"a", "unclosed quote"),
+            ("e", "`e` isn't in the words file"),
+            ("a NEAR/x b", "invalid distance in `NEAR/x`"),
+            ("a NEAR/-1 b", "invalid distance in `NEAR/-1`"),
+            ("(a OR b) NEAR/2 c", "`NEAR/2` only works between two words"),
+            ("NOT a NEAR/2 b", "`NEAR/2` only works between two words"),
+        ] {
+            assert_eq!(parse(query).unwrap_err(), message, "{query}");
+        }
+    }
+}
@@ -0,0 +1,41 @@
+//! Parsing amounts of bytes like `4MiB` or `100M`.
+
+use std::error::Error;
+use std::fmt;
+
+/// Error returned when parsing an invalid amount of bytes.
+#[derive(Debug)]
+pub struct InvalidSize(String);
+
+impl fmt::Display for InvalidSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid size `{}`, expected a number of bytes like `512`, `4K` or `4MiB`",
+            self.0
+        )
+    }
+}
+
+impl Error for InvalidSize {}
+
+/// Parse an amount of bytes with an optional unit. Units are powers of 1024, so `K`, `KB` and
+/// `KiB` all mean the same, as do `M`, `G` and `T` with their longer forms.
+pub fn parse(s: &str) -> Result<u64, InvalidSize> {
+    let invalid = || InvalidSize(s.to_owned());
+    let s = s.trim();
+    let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(digits);
+
+    let shift = match unit.trim_start().to_ascii_uppercase().as_str() {
+        "" | "B" => 0,
+        "K" | "KB" | "KIB" => 10,
+        "M" | "MB" | "MIB" => 20,
+        "G" | "GB" | "GIB" => 30,
+        "T" | "TB" | "TIB" => 40,
+        _ => return Err(invalid()),
+    };
+
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    number.checked_mul(1 << shift).ok_or_else(invalid)
+}
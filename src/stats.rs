@@ -0,0 +1,178 @@
+//! Statistics about a run, to tune the chunk size and word lists.
+//!
+//! Recording them costs a clock reading and a few atomic additions per chunk, so they are always
+//! collected and only printed if asked for.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::aho_corasick;
+use crate::threads;
+
+const MIB: f64 = 1024.0 * 1024.0;
+
+/// Statistics of a whole run.
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    pub automata: Vec<Automaton>,
+    /// Seconds spent building the automata.
+    pub build_secs: f64,
+    /// Seconds spent scanning the article.
+    pub scan_secs: f64,
+    pub bytes: u64,
+    pub bytes_per_sec: f64,
+    pub matches: u64,
+    pub chunks: u64,
+    /// Seconds each thread of the pool spent on chunks.
+    pub busy_secs: Vec<f64>,
+}
+
+/// Sizes of one of the Aho-Corasick automata.
+#[derive(Debug, Serialize)]
+pub struct Automaton {
+    /// Which words the automaton searches, as is or case folded.
+    pub name: &'static str,
+    pub patterns: usize,
+    #[serde(flatten)]
+    pub sizes: aho_corasick::Stats,
+}
+
+/// Collects the statistics while scanning.
+#[derive(Debug)]
+pub struct Recorder {
+    start: Instant,
+    built: Duration,
+    /// Total count of all words before the scan.
+    matches: u64,
+    bytes: AtomicU64,
+    chunks: AtomicU64,
+    /// Nanoseconds each thread of the pool spent on chunks.
+    busy: Vec<AtomicU64>,
+}
+
+impl Recorder {
+    /// Start recording, given the total count of all words so far.
+    pub fn new(matches: u64) -> Self {
+        Self {
+            start: Instant::now(),
+            built: Duration::ZERO,
+            matches,
+            bytes: AtomicU64::default(),
+            chunks: AtomicU64::default(),
+            busy: (0..threads::count()).map(|_| AtomicU64::default()).collect(),
+        }
+    }
+
+    /// Mark the end of building the automata and the start of the scan.
+    pub fn built(&mut self) {
+        self.built = self.start.elapsed();
+    }
+
+    /// Add a chunk of `len` bytes that the current thread started working on at `started`.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn chunk(&self, len: usize, started: Instant) {
+        self.bytes.fetch_add(len as u64, Ordering::Relaxed);
+        self.chunks.fetch_add(1, Ordering::Relaxed);
+
+        let thread = rayon::current_thread_index().unwrap_or_default();
+        if let Some(busy) = self.busy.get(thread) {
+            busy.fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// Finish recording, given the total count of all words after the scan.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn finish(self, automata: Vec<Automaton>, matches: u64) -> Stats {
+        let scan = self.start.elapsed().saturating_sub(self.built);
+        let bytes = self.bytes.into_inner();
+
+        Stats {
+            automata,
+            build_secs: self.built.as_secs_f64(),
+            scan_secs: scan.as_secs_f64(),
+            bytes,
+            bytes_per_sec: bytes as f64 / scan.as_secs_f64().max(f64::EPSILON),
+            matches: matches - self.matches,
+            chunks: self.chunks.into_inner(),
+            busy_secs: self
+                .busy
+                .into_iter()
+                .map(|busy| Duration::from_nanos(busy.into_inner()).as_secs_f64())
+                .collect(),
+        }
+    }
+}
+
+impl Stats {
+    pub fn write(&self, format: Format, mut out: impl io::Write) -> io::Result<()> {
+        if format == Format::Json {
+            serde_json::to_writer_pretty(&mut out, self)?;
+            return writeln!(out);
+        }
+
+        for a in &self.automata {
+            let s = &a.sizes;
+            writeln!(out, "automaton {}: {} patterns", a.name, a.patterns)?;
+            writeln!(out, "  NFA states    {} ({} heap bytes)", s.nfa_states, s.nfa_heap_bytes)?;
+            if s.dfa {
+                writeln!(
+                    out,
+                    "  DFA states    {} ({} heap bytes)",
+                    s.dfa_states, s.dfa_heap_bytes
+                )?;
+                writeln!(out, "  match states  {} (max_match {})", s.match_states, s.max_match)?;
+            } else {
+                writeln!(out, "  DFA           not built, searching the NFA")?;
+                writeln!(out, "  match states  {}", s.match_states)?;
+            }
+        }
+        writeln!(out, "build         {:.3} s", self.build_secs)?;
+        writeln!(out, "scan          {:.3} s", self.scan_secs)?;
+        writeln!(out, "bytes         {} ({:.1} MiB/s)", self.bytes, self.bytes_per_sec / MIB)?;
+        writeln!(out, "matches       {}", self.matches)?;
+        writeln!(out, "chunks        {}", self.chunks)?;
+        for (thread, busy) in self.busy_secs.iter().enumerate() {
+            writeln!(out, "thread {thread:<6} {busy:.3} s busy")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Output format of the statistics.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = UnknownFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(UnknownFormat(s.to_owned())),
+        }
+    }
+}
+
+/// Error returned when parsing an unsupported statistics format.
+#[derive(Debug)]
+pub struct UnknownFormat(String);
+
+impl fmt::Display for UnknownFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown statistics format `{}`, expected one of `text` or `json`", self.0)
+    }
+}
+
+impl Error for UnknownFormat {}
@@ -0,0 +1,156 @@
+//! Controlling the threads that scan the article.
+//!
+//! All parallel work runs on rayon's global pool, which is set up once at the start. With a single
+//! thread no pool is started at all and chunks are scanned one after another on the main thread,
+//! which also makes the order of printed positions deterministic.
+
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use rayon::prelude::*;
+
+/// Environment variable with the number of threads, used if not given as option.
+pub const ENV: &str = "WORDCOUNT_THREADS";
+
+static SEQUENTIAL: AtomicBool = AtomicBool::new(false);
+
+/// Set up the thread pool with the given number of threads or, if not given, as many as set in the
+/// environment. Zero or neither means one thread per core.
+pub fn init(threads: Option<usize>) -> Result<(), Box<dyn Error>> {
+    let threads = match threads {
+        Some(threads) => threads,
+        None => match env::var(ENV) {
+            Ok(threads) => threads.trim().parse().map_err(|_| InvalidThreads(threads))?,
+            Err(_) => 0,
+        },
+    };
+
+    match threads {
+        0 => Ok(()),
+        1 => {
+            SEQUENTIAL.store(true, Ordering::Relaxed);
+            Ok(())
+        }
+        _ => Ok(rayon::ThreadPoolBuilder::new().num_threads(threads).build_global()?),
+    }
+}
+
+/// Number of threads that work on chunks.
+pub fn count() -> usize {
+    if SEQUENTIAL.load(Ordering::Relaxed) {
+        1
+    } else {
+        rayon::current_num_threads()
+    }
+}
+
+/// Call `f` for every item, on the thread pool or one after another with a single thread.
+pub fn for_each<I, F>(items: I, f: F)
+where
+    I: Iterator + Send,
+    I::Item: Send,
+    F: Fn(I::Item) + Send + Sync,
+{
+    if SEQUENTIAL.load(Ordering::Relaxed) {
+        items.for_each(f);
+    } else {
+        items.par_bridge().for_each(f);
+    }
+}
+
+/// Like [`for_each`], but stops at the first error.
+pub fn try_for_each<I, F, E>(mut items: I, f: F) -> Result<(), E>
+where
+    I: Iterator + Send,
+    I::Item: Send,
+    F: Fn(I::Item) -> Result<(), E> + Send + Sync,
+    E: Send,
+{
+    if SEQUENTIAL.load(Ordering::Relaxed) {
+        items.try_for_each(f)
+    } else {
+        items.par_bridge().try_for_each(f)
+    }
+}
+
+/// Error returned when the environment holds an invalid number of threads.
+#[derive(Debug)]
+pub struct InvalidThreads(String);
+
+impl fmt::Display for InvalidThreads {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid number of threads `{}` in {ENV}", self.0)
+    }
+}
+
+impl Error for InvalidThreads {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+    use std::sync::atomic::AtomicU64;
+    use std::sync::Mutex;
+
+    use indexmap::IndexMap;
+
+    use super::*;
+    use crate::ahocorasick::{self, Settings};
+    use crate::words::{self, Format};
+
+    /// Held while running on a single thread, which is a global setting.
+    static LOCK: Mutex<()> = Mutex::new(());
+
+    fn sequential<T>(f: impl FnOnce() -> T) -> T {
+        let _lock = LOCK.lock().unwrap();
+        SEQUENTIAL.store(true, Ordering::Relaxed);
+        let result = f();
+        SEQUENTIAL.store(false, Ordering::Relaxed);
+        result
+    }
+
+    fn parallel<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+        let _lock = LOCK.lock().unwrap();
+        rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap().install(f)
+    }
+
+    /// Count a few words in the article, split into many small chunks.
+    fn count(article: &str) -> Vec<u64> {
+        let words = words::parse("the\nof\nand\nsea\n", Format::Plain).unwrap();
+        let words: IndexMap<_, _> =
+            words.words().iter().map(|w| (w, AtomicU64::default())).collect();
+        let settings = Settings { chunk_size: 64, ..Settings::default() };
+        ahocorasick::process(&words, BufReader::new(article.as_bytes()), settings).unwrap();
+        words.values().map(|count| count.load(Ordering::SeqCst)).collect()
+    }
+
+    #[test]
+    fn sequential_in_order() {
+        let visited = Mutex::new(vec![]);
+        sequential(|| for_each(0..100, |i| visited.lock().unwrap().push(i)));
+        assert_eq!(visited.into_inner().unwrap(), (0..100).collect::<Vec<_>>());
+
+        let visited = Mutex::new(vec![]);
+        let result = sequential(|| {
+            try_for_each(0..100, |i| {
+                visited.lock().unwrap().push(i);
+                if i == 42 {
+                    Err(i)
+                } else {
+                    Ok(())
+                }
+            })
+        });
+        assert_eq!(result, Err(42));
+        assert_eq!(visited.into_inner().unwrap(), (0..=42).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn same_counts_on_any_number_of_threads() {
+        let article = "the sea and the ship\nof the sea\n".repeat(50);
+        let expected = vec![150, 50, 50, 100];
+        assert_eq!(sequential(|| count(&article)), expected);
+        assert_eq!(parallel(|| count(&article)), expected);
+    }
+}
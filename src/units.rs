@@ -0,0 +1,450 @@
+//! Counting per line, paragraph or document instead of over the whole article.
+//!
+//! Every unit of the article gets the set of words found within it, which gives the amount of units
+//! containing each word (the document frequency), the queries each unit satisfies and optionally
+//! a term-document matrix.
+//!
+//! Paragraphs and documents may span several chunks. Each chunk handles the units that lie entirely
+//! within it right away and keeps the parts of units that may continue into a neighbouring chunk.
+//! Once all chunks are done, these parts are walked in order and joined before they're counted.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use regex::Regex;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::query::Query;
+
+/// What the article is split into.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Kind {
+    Line,
+    /// Lines separated by blank lines.
+    Paragraph,
+    /// Lines starting at a separator line.
+    Document,
+}
+
+impl FromStr for Kind {
+    type Err = UnknownUnit;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "line" => Ok(Self::Line),
+            "paragraph" => Ok(Self::Paragraph),
+            "document" => Ok(Self::Document),
+            _ => Err(UnknownUnit(s.to_owned())),
+        }
+    }
+}
+
+/// Error returned when parsing an unsupported unit.
+#[derive(Debug)]
+pub struct UnknownUnit(String);
+
+impl fmt::Display for UnknownUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown unit `{}`, expected one of `line`, `paragraph` or `document`", self.0)
+    }
+}
+
+impl Error for UnknownUnit {}
+
+/// Error returned when documents are requested without a way to tell them apart.
+#[derive(Debug)]
+pub struct MissingSeparator;
+
+impl fmt::Display for MissingSeparator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("counting per document needs a document separator")
+    }
+}
+
+impl Error for MissingSeparator {}
+
+/// How the article is split into units.
+#[derive(Debug)]
+pub struct Unit {
+    kind: Kind,
+    /// Pattern of the lines that start a new document.
+    separator: Option<Regex>,
+}
+
+impl Unit {
+    pub fn new(kind: Kind, separator: Option<Regex>) -> Result<Self, MissingSeparator> {
+        if kind == Kind::Document && separator.is_none() {
+            return Err(MissingSeparator);
+        }
+
+        Ok(Self { kind, separator })
+    }
+
+    /// Plural name of the unit, for headers of the output.
+    pub const fn plural(&self) -> &'static str {
+        match self.kind {
+            Kind::Line => "lines",
+            Kind::Paragraph => "paragraphs",
+            Kind::Document => "documents",
+        }
+    }
+
+    fn is_separator(&self, line: &str) -> bool {
+        let line = line.trim_end_matches(['\r', '\n']);
+        self.separator.as_ref().is_some_and(|re| re.is_match(line))
+    }
+
+    /// Title of the document started by a separator line, if the separator has a capture group
+    /// named `title`.
+    fn title(&self, line: &str) -> Option<String> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        let captures = self.separator.as_ref()?.captures(line)?;
+        captures.name("title").map(|title| title.as_str().to_owned())
+    }
+
+    /// Split a chunk into the byte ranges of its units. Units that may continue from the previous
+    /// chunk or into the next one are marked as open at that side.
+    fn split(&self, chunk: &str) -> Vec<Range> {
+        let mut ranges: Vec<Range> = vec![];
+        let mut current: Option<Range> = None;
+        let mut start = 0;
+
+        for line in chunk.split_inclusive('\n') {
+            let end = start + line.len();
+
+            match self.kind {
+                // Chunks end after a line break, so only the last line of an article is open.
+                Kind::Line => ranges.push(Range {
+                    open_end: !line.ends_with('\n'),
+                    ..Range::new(start, end, start == 0)
+                }),
+                Kind::Paragraph if line.trim().is_empty() => ranges.extend(current.take()),
+                Kind::Document if self.is_separator(line) => {
+                    let title = self.title(line);
+                    ranges
+                        .extend(current.replace(Range { title, ..Range::new(start, end, false) }));
+                }
+                Kind::Paragraph | Kind::Document => match &mut current {
+                    Some(range) => range.end = end,
+                    None => current = Some(Range::new(start, end, start == 0)),
+                },
+            }
+
+            start = end;
+        }
+
+        // Paragraphs and documents that are still going on at the end may continue in the next
+        // chunk.
+        ranges.extend(current.map(|range| Range { open_end: true, ..range }));
+        ranges
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Range {
+    start: usize,
+    end: usize,
+    open_start: bool,
+    open_end: bool,
+    /// Title of the document, taken from its separator line.
+    title: Option<String>,
+}
+
+impl Range {
+    const fn new(start: usize, end: usize, open_start: bool) -> Self {
+        Self { start, end, open_start, open_end: false, title: None }
+    }
+}
+
+/// The words found in a single unit, with the token positions of their matches relative to the
+/// unit's start. Positions are only tracked if needed, otherwise they're all zero.
+#[derive(Debug, Default)]
+pub struct Found {
+    positions: HashMap<usize, Vec<u64>>,
+    /// Length of the unit in tokens, if positions are tracked.
+    tokens: u64,
+}
+
+impl Found {
+    pub fn contains(&self, id: usize) -> bool {
+        self.positions.contains_key(&id)
+    }
+
+    pub fn positions(&self, id: usize) -> &[u64] {
+        self.positions.get(&id).map_or(&[], Vec::as_slice)
+    }
+
+    /// A unit with matches of the given words at the given token positions.
+    #[cfg(test)]
+    pub fn new(matches: &[(usize, u64)]) -> Self {
+        let mut found = Self::default();
+        for &(id, position) in matches {
+            found.positions.entry(id).or_default().push(position);
+            found.tokens = found.tokens.max(position + 1);
+        }
+        found
+    }
+
+    /// Append the matches of the following part of the same unit.
+    fn extend(&mut self, other: Self) {
+        for (id, positions) in other.positions {
+            let shifted = positions.into_iter().map(|p| p + self.tokens);
+            self.positions.entry(id).or_default().extend(shifted);
+        }
+        self.tokens += other.tokens;
+    }
+}
+
+/// A unit or part of a unit, waiting to be counted once all chunks are done.
+struct Part {
+    found: Found,
+    title: Option<String>,
+    open_start: bool,
+    open_end: bool,
+}
+
+/// Counts of all units.
+#[derive(Debug, Default)]
+pub struct UnitReport {
+    /// The total amount of units.
+    pub units: u64,
+    /// For each word, the amount of units it was found in.
+    pub frequency: Vec<u64>,
+    /// For each query, the amount of units that satisfy it.
+    pub queries: Vec<u64>,
+    /// For each unit that contains any word, its title or 1-based number and the count of each
+    /// word.
+    pub rows: Vec<(String, Vec<(usize, u64)>)>,
+}
+
+/// Collects the units of all chunks.
+pub struct Units<'a> {
+    unit: &'a Unit,
+    queries: &'a [Query],
+    /// Keep every unit in order, so the term-document matrix can be built.
+    rows: bool,
+    positions: bool,
+    total: AtomicU64,
+    frequency: Vec<AtomicU64>,
+    satisfied: Vec<AtomicU64>,
+    parts: Mutex<Vec<(u64, Vec<Part>)>>,
+}
+
+impl<'a> Units<'a> {
+    pub fn new(unit: &'a Unit, queries: &'a [Query], words: usize, rows: bool) -> Self {
+        Self {
+            unit,
+            queries,
+            rows,
+            positions: Query::needs_positions(queries),
+            total: AtomicU64::default(),
+            frequency: (0..words).map(|_| AtomicU64::default()).collect(),
+            satisfied: queries.iter().map(|_| AtomicU64::default()).collect(),
+            parts: Mutex::default(),
+        }
+    }
+
+    /// Add the matches of a chunk, given by their sorted byte position within the chunk and the
+    /// index of their word.
+    pub fn add(&self, offset: u64, chunk: &str, matches: &[(usize, usize)]) {
+        let ends: Vec<_> = if self.positions {
+            chunk.unicode_word_indices().map(|(i, t)| i + t.len()).collect()
+        } else {
+            vec![]
+        };
+        let token = |p: usize| ends.partition_point(|&end| end <= p) as u64;
+
+        // Units without any matches all satisfy the same queries, so they're only evaluated once.
+        let mut empty = 0;
+        let mut parts = vec![];
+        let mut matches = matches.iter().peekable();
+
+        for range in self.unit.split(chunk) {
+            // Skip matches within lines that don't belong to any unit.
+            while matches.next_if(|&&(p, _)| p < range.start).is_some() {}
+
+            let first = token(range.start);
+            let mut found = Found { positions: HashMap::new(), tokens: token(range.end) - first };
+            while let Some(&(p, id)) = matches.next_if(|&&(p, _)| p < range.end) {
+                found.positions.entry(id).or_default().push(token(p) - first);
+            }
+
+            if self.rows || range.open_start || range.open_end {
+                parts.push(Part {
+                    found,
+                    title: range.title,
+                    open_start: range.open_start,
+                    open_end: range.open_end,
+                });
+            } else if found.positions.is_empty() {
+                empty += 1;
+            } else {
+                self.count(&found);
+            }
+        }
+
+        if empty > 0 {
+            self.count_empty(empty);
+        }
+
+        self.parts.lock().unwrap().push((offset, parts));
+    }
+
+    fn count(&self, found: &Found) {
+        self.total.fetch_add(1, Ordering::SeqCst);
+
+        for &id in found.positions.keys() {
+            self.frequency[id].fetch_add(1, Ordering::SeqCst);
+        }
+
+        for (satisfied, query) in self.satisfied.iter().zip(self.queries) {
+            if query.matches(found) {
+                satisfied.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    fn count_empty(&self, n: u64) {
+        self.total.fetch_add(n, Ordering::SeqCst);
+
+        let found = Found::default();
+        for (satisfied, query) in self.satisfied.iter().zip(self.queries) {
+            if query.matches(&found) {
+                satisfied.fetch_add(n, Ordering::SeqCst);
+            }
+        }
+    }
+
+    /// Join the parts of units that span chunks, count them and return the counts of all units.
+    pub fn finish(self) -> UnitReport {
+        let mut chunks = std::mem::take(&mut *self.parts.lock().unwrap());
+        chunks.sort_unstable_by_key(|&(offset, _)| offset);
+
+        let mut rows = vec![];
+        let mut finish = |part: Option<Part>| {
+            if let Some(Part { found, title, .. }) = part {
+                self.count(&found);
+                if self.rows {
+                    let number = self.total.load(Ordering::SeqCst);
+                    let mut row: Vec<_> =
+                        found.positions.into_iter().map(|(id, p)| (id, p.len() as u64)).collect();
+                    if !row.is_empty() {
+                        row.sort_unstable();
+                        rows.push((title.unwrap_or_else(|| number.to_string()), row));
+                    }
+                }
+            }
+        };
+
+        let mut current: Option<Part> = None;
+        for (_, parts) in chunks {
+            let mut parts = parts.into_iter();
+
+            let Some(first) = parts.next() else {
+                finish(current.take());
+                continue;
+            };
+
+            match current.take() {
+                Some(mut c) if c.open_end && first.open_start => {
+                    c.found.extend(first.found);
+                    c.open_end = first.open_end;
+                    current = Some(c);
+                }
+                c => {
+                    finish(c);
+                    current = Some(first);
+                }
+            }
+
+            for part in parts {
+                finish(current.replace(part));
+            }
+        }
+        finish(current);
+
+        UnitReport {
+            units: self.total.into_inner(),
+            frequency: self.frequency.into_iter().map(AtomicU64::into_inner).collect(),
+            queries: self.satisfied.into_iter().map(AtomicU64::into_inner).collect(),
+            rows,
+        }
+    }
+}
+
+/// Write the term-document matrix as CSV, with one row per unit and word found in it. Besides the
+/// count, each row has the TF-IDF weight of the word in the unit.
+pub fn write_matrix(report: &UnitReport, ids: &[String], out: impl io::Write) -> io::Result<()> {
+    let mut out = csv::Writer::from_writer(out);
+    out.write_record(["unit", "word", "count", "tfidf"])?;
+
+    #[allow(clippy::cast_precision_loss)]
+    for (unit, row) in &report.rows {
+        for &(id, count) in row {
+            let idf = (report.units as f64 / report.frequency[id] as f64).ln();
+            let tfidf = count as f64 * idf;
+            out.write_record([
+                unit.clone(),
+                ids[id].clone(),
+                count.to_string(),
+                format!("{tfidf:.6}"),
+            ])?;
+        }
+    }
+
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+    use std::sync::atomic::AtomicU64;
+
+    use indexmap::IndexMap;
+
+    use super::*;
+    use crate::ahocorasick::{self, Settings};
+    use crate::chunks::CHUNK_SIZE;
+    use crate::words::{self, Format};
+
+    /// Paragraphs of different lengths, with lines both shorter and longer than a small chunk.
+    fn article() -> String {
+        let mut article = String::new();
+        for p in 0..40 {
+            for l in 0..p * 7 % 23 {
+                article += &l.to_string();
+                article += ": the cat sat near the dog";
+                article += &" and then the dog ran".repeat(p * l % 9);
+                article += if (p + l) % 5 == 0 { " cat\n" } else { "\n" };
+            }
+            article += "\n";
+        }
+        article
+    }
+
+    fn count(kind: Kind, chunk_size: u64) -> (u64, Vec<u64>, Vec<u64>) {
+        let words = words::parse("cat\ndog\nran\n", Format::Plain).unwrap();
+        let queries = [Query::parse("cat NEAR/2 dog", &words).unwrap()];
+        let words: IndexMap<_, _> =
+            words.words().iter().map(|w| (w, AtomicU64::default())).collect();
+        let unit = Unit::new(kind, None).unwrap();
+        let settings =
+            Settings { unit: Some(&unit), queries: &queries, chunk_size, ..Settings::default() };
+        let article = article();
+        let report = ahocorasick::process(&words, BufReader::new(article.as_bytes()), settings);
+        let report = report.unwrap().units.unwrap();
+        (report.units, report.frequency, report.queries)
+    }
+
+    #[test]
+    fn same_units_for_any_chunk_size() {
+        for kind in [Kind::Line, Kind::Paragraph] {
+            assert_eq!(count(kind, 64), count(kind, CHUNK_SIZE));
+        }
+    }
+}
@@ -0,0 +1,381 @@
+//! Counting every distinct token or n-gram of the article, instead of a predefined list of words.
+//!
+//! Chunks of the article are tokenized in parallel and their counts merged into a fixed amount of
+//! shards, each guarded by its own lock, so threads rarely wait on each other. If spilling is
+//! enabled, a shard that grows too large is written to a temporary file as a run sorted by token.
+//! In the end, all runs are merged again, so memory usage stays bounded no matter how many distinct
+//! tokens the article contains.
+//!
+//! Each token is counted by the chunk owning its start, so tokens cut at the end of a chunk are
+//! still seen completely through its overlap. N-grams may span several chunks. Each chunk only
+//! counts the n-grams within itself and keeps its first and last `n - 1` tokens. Once all chunks
+//! are done, these edges are walked in order to count the n-grams that cross from one chunk into
+//! the next.
+
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::hash_map::RandomState;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::error::Error;
+use std::fs::File;
+use std::hash::BuildHasher;
+use std::io::{self, BufReader, BufWriter, Read, Seek, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::chunks::Chunks;
+use crate::fold::Folding;
+use crate::memory::{self, Budget, OutOfMemory};
+
+/// The amount of shards the counts are split into.
+const SHARDS: usize = 64;
+
+/// The maximum amount of distinct tokens kept in memory per shard, before spilling them to disk.
+const SPILL_LIMIT: usize = (1 << 20) / SHARDS;
+
+/// How many bytes chunks overlap. Tokens longer than that may be cut where a chunk ends.
+const OVERLAP: usize = 1024;
+
+/// Token counts sorted by token, either in memory or read back from disk.
+type Run<'a> = Box<dyn Iterator<Item = io::Result<(String, u64)>> + 'a>;
+
+/// How the article is split into tokens.
+#[derive(Clone, Debug, Default)]
+pub enum Tokenizer {
+    /// Split on Unicode word boundaries, dropping everything that isn't a word like punctuation.
+    #[default]
+    Words,
+    /// Split on any of the given characters and line breaks.
+    Separators(Vec<char>),
+}
+
+impl Tokenizer {
+    /// Call `f` with the position and text of every token.
+    fn tokenize<'a>(&self, text: &'a str, mut f: impl FnMut(usize, &'a str)) {
+        match self {
+            Self::Words => text.unicode_word_indices().for_each(|(start, token)| f(start, token)),
+            Self::Separators(separators) => {
+                let mut start = 0;
+                for (end, c) in text.char_indices() {
+                    if c == '\n' || c == '\r' || separators.contains(&c) {
+                        if end > start {
+                            f(start, &text[start..end]);
+                        }
+                        start = end + c.len_utf8();
+                    }
+                }
+                if start < text.len() {
+                    f(start, &text[start..]);
+                }
+            }
+        }
+    }
+}
+
+/// Options for counting all tokens.
+#[derive(Clone, Debug, Default)]
+pub struct Settings<'a> {
+    pub folding: Folding,
+    pub tokenizer: Tokenizer,
+    /// Leave out all tokens that were found less often.
+    pub min_count: u64,
+    /// Write counts to temporary files instead of keeping them all in memory.
+    pub spill: bool,
+    /// Count sequences of this many consecutive tokens, joined by a space.
+    pub ngrams: usize,
+    /// Only count n-grams whose tokens are all part of this set.
+    pub phrases: Option<HashSet<String>>,
+    /// Bytes of article per chunk.
+    pub chunk_size: u64,
+    pub budget: Option<&'a Budget>,
+    /// Add the number of tokens found to this counter as they are found, to report the progress.
+    pub found: Option<&'a AtomicU64>,
+}
+
+/// The counts of one shard, plus the runs that were already spilled to disk.
+#[derive(Default)]
+struct Shard {
+    counts: HashMap<String, u64>,
+    runs: Vec<File>,
+}
+
+/// Count every token or n-gram of the article and print them as a table. The table is sorted by
+/// count, starting with the most frequent one. If any counts had to be spilled to disk, it's sorted
+/// by token instead, as that is the order the runs are merged in.
+pub fn process<R>(article: BufReader<R>, settings: &Settings<'_>) -> Result<(), Box<dyn Error>>
+where
+    R: Read + Send,
+{
+    let Settings { folding, tokenizer, min_count, spill, ngrams, phrases, found, .. } = settings;
+    let n = (*ngrams).max(1);
+    let counter = Counter::new(*spill);
+    let edges = Mutex::new(vec![]);
+
+    // Turn n consecutive tokens into the key they are counted under, unless they aren't a phrase.
+    let key = |gram: &[&'_ str]| -> Option<String> {
+        if phrases.as_ref().is_some_and(|p| gram.iter().any(|t| !p.contains(*t))) {
+            return None;
+        }
+        Some(gram.join(" "))
+    };
+
+    chunks(article, settings)?.read_ahead(|chunk| {
+        let text = folding.fold_mapped(&chunk.text);
+
+        let mut tokens = vec![];
+        tokenizer.tokenize(text.text(), |start, token| {
+            if chunk.owns(text.original_start(start)) {
+                tokens.push(token);
+            }
+        });
+        if let Some(found) = found {
+            found.fetch_add(tokens.len() as u64, Ordering::Relaxed);
+        }
+
+        // Count locally first, so every shard is only locked once per chunk.
+        let mut local = Counter::local();
+        for gram in tokens.windows(n) {
+            match gram {
+                [token] if phrases.is_none() => counter.count(&mut local, Cow::Borrowed(*token)),
+                _ => {
+                    if let Some(key) = key(gram) {
+                        counter.count(&mut local, Cow::Owned(key));
+                    }
+                }
+            }
+        }
+
+        // N-grams reaching into the next chunk are counted once all chunks are done.
+        if n > 1 {
+            let k = (n - 1).min(tokens.len());
+            let own = |tokens: &[&str]| tokens.iter().map(|&t| t.to_owned()).collect::<Vec<_>>();
+            let edge = Edge { head: own(&tokens[..k]), tail: own(&tokens[tokens.len() - k..]) };
+            edges.lock().unwrap().push((chunk.own().0, edge));
+        }
+
+        counter.add(local)?;
+        Ok(true)
+    })?;
+
+    let mut edges = edges.into_inner().unwrap();
+    edges.sort_unstable_by_key(|&(offset, _)| offset);
+
+    // Go through the edges of all chunks in order, keeping the last n - 1 tokens before each chunk,
+    // to find the n-grams that start before the chunk and end within it.
+    let mut local = Counter::local();
+    let mut carry: Vec<String> = vec![];
+    for (_, Edge { head, tail }) in edges {
+        let short = head.len() < n - 1;
+        let joined: Vec<&str> = carry.iter().chain(&head).map(String::as_str).collect();
+
+        for (start, gram) in joined.windows(n).enumerate() {
+            if start < carry.len() {
+                if let Some(key) = key(gram) {
+                    counter.count(&mut local, Cow::Owned(key));
+                }
+            }
+        }
+
+        carry = if short {
+            let skip = joined.len().saturating_sub(n - 1);
+            joined[skip..].iter().map(|&t| t.to_owned()).collect()
+        } else {
+            tail
+        };
+    }
+    counter.add(local)?;
+
+    let shards: Vec<_> = counter.shards.into_iter().map(|s| s.into_inner().unwrap()).collect();
+    let mut out = BufWriter::new(io::stdout().lock());
+
+    if shards.iter().all(|s| s.runs.is_empty()) {
+        let mut counts: Vec<_> = shards
+            .into_iter()
+            .flat_map(|s| s.counts)
+            .filter(|&(_, count)| count >= *min_count)
+            .collect();
+        counts.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        for (token, count) in counts {
+            writeln!(out, "{}\t{count}", token.escape_debug())?;
+        }
+    } else {
+        let mut runs: Vec<Run<'_>> = vec![];
+        for shard in shards {
+            runs.push(Box::new(sorted(shard.counts).into_iter().map(Ok)));
+            for run in shard.runs {
+                runs.push(Box::new(read_run(run)));
+            }
+        }
+
+        merge(runs, |token, count| {
+            if count >= *min_count {
+                writeln!(out, "{}\t{count}", token.escape_debug())?;
+            }
+            Ok(())
+        })?;
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+/// Split the article into chunks, as many of them at once as fit into the budget.
+fn chunks<R: Read + Send>(
+    article: BufReader<R>,
+    settings: &Settings<'_>,
+) -> Result<Chunks<BufReader<R>>, OutOfMemory> {
+    let size = usize::try_from(settings.chunk_size.max(1)).unwrap_or(usize::MAX);
+    let chunks = Chunks::new(article, size, OVERLAP);
+    let Some(budget) = settings.budget else {
+        return Ok(chunks);
+    };
+
+    // Besides the chunk itself, there is its folded text, unless folding leaves it as it is.
+    let copies = 1 + u64::from(!settings.folding.is_identity());
+    let buffers = memory::buffers(budget, chunks.buffers(), chunks.buffer_size() as u64 * copies)?;
+    Ok(chunks.limit(buffers))
+}
+
+/// The first and last n - 1 tokens of a chunk. If the chunk has fewer tokens, both are all of them.
+struct Edge {
+    head: Vec<String>,
+    tail: Vec<String>,
+}
+
+/// Counts split into shards, each guarded by its own lock.
+struct Counter {
+    hasher: RandomState,
+    shards: Vec<Mutex<Shard>>,
+    spill: bool,
+}
+
+impl Counter {
+    fn new(spill: bool) -> Self {
+        let shards = (0..SHARDS).map(|_| Mutex::new(Shard::default())).collect();
+        Self { hasher: RandomState::new(), shards, spill }
+    }
+
+    /// Create empty local counts for every shard, to be filled with [`Counter::count`].
+    fn local<'a>() -> Vec<HashMap<Cow<'a, str>, u64>> {
+        vec![HashMap::new(); SHARDS]
+    }
+
+    fn count<'a>(&self, local: &mut [HashMap<Cow<'a, str>, u64>], token: Cow<'a, str>) {
+        // Truncating the hash is fine, only the lowest bits are needed.
+        #[allow(clippy::cast_possible_truncation)]
+        let shard = self.hasher.hash_one(&*token) as usize % SHARDS;
+        *local[shard].entry(token).or_default() += 1;
+    }
+
+    /// Add local counts to the shards, spilling them to disk if they grow too large.
+    fn add(&self, local: Vec<HashMap<Cow<'_, str>, u64>>) -> io::Result<()> {
+        for (shard, local) in self.shards.iter().zip(local) {
+            if local.is_empty() {
+                continue;
+            }
+
+            let mut shard = shard.lock().unwrap();
+            for (token, n) in local {
+                match shard.counts.get_mut(&*token) {
+                    Some(count) => *count += n,
+                    None => {
+                        shard.counts.insert(token.into_owned(), n);
+                    }
+                }
+            }
+
+            if self.spill && shard.counts.len() > SPILL_LIMIT {
+                let run = write_run(sorted(std::mem::take(&mut shard.counts)))?;
+                shard.runs.push(run);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn sorted(counts: HashMap<String, u64>) -> Vec<(String, u64)> {
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_unstable();
+    counts
+}
+
+/// Write sorted counts to a temporary file, each as the length of the token, the token itself and
+/// its count.
+fn write_run(counts: Vec<(String, u64)>) -> io::Result<File> {
+    let mut out = BufWriter::new(tempfile::tempfile()?);
+    for (token, count) in counts {
+        // Tokens are part of chunks that are much smaller than 4 GiB.
+        #[allow(clippy::cast_possible_truncation)]
+        out.write_all(&(token.len() as u32).to_le_bytes())?;
+        out.write_all(token.as_bytes())?;
+        out.write_all(&count.to_le_bytes())?;
+    }
+
+    let mut file = out.into_inner().map_err(io::IntoInnerError::into_error)?;
+    file.rewind()?;
+    Ok(file)
+}
+
+/// Read back the counts written by [`write_run`].
+fn read_run(file: File) -> impl Iterator<Item = io::Result<(String, u64)>> {
+    let mut input = BufReader::new(file);
+
+    std::iter::from_fn(move || {
+        let mut len = [0; 4];
+        match input.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return None,
+            Err(e) => return Some(Err(e)),
+        }
+
+        let read = |input: &mut BufReader<File>| {
+            let mut token = vec![0; u32::from_le_bytes(len) as usize];
+            input.read_exact(&mut token)?;
+            let token = String::from_utf8(token)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+            let mut count = [0; 8];
+            input.read_exact(&mut count)?;
+            Ok((token, u64::from_le_bytes(count)))
+        };
+
+        Some(read(&mut input))
+    })
+}
+
+/// Merge sorted runs, calling `f` with the total count of every token in order.
+fn merge(mut runs: Vec<Run<'_>>, mut f: impl FnMut(&str, u64) -> io::Result<()>) -> io::Result<()> {
+    let mut heap = BinaryHeap::new();
+    for (i, run) in runs.iter_mut().enumerate() {
+        if let Some(next) = run.next() {
+            heap.push(Reverse((next?, i)));
+        }
+    }
+
+    let mut current: Option<(String, u64)> = None;
+
+    while let Some(Reverse(((token, count), i))) = heap.pop() {
+        if let Some(next) = runs[i].next() {
+            heap.push(Reverse((next?, i)));
+        }
+
+        match &mut current {
+            Some((t, c)) if *t == token => *c += count,
+            _ => {
+                if let Some((t, c)) = current.replace((token, count)) {
+                    f(&t, c)?;
+                }
+            }
+        }
+    }
+
+    if let Some((t, c)) = current {
+        f(&t, c)?;
+    }
+
+    Ok(())
+}
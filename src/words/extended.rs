@@ -0,0 +1,208 @@
+//! The extended words format, which allows comments, escape sequences and per-pattern flags:
+//!
+//! ```txt
+//! # Lines starting with a hash are comments, blank lines are skipped.
+//! cat
+//! \#hashtag
+//! tab\there
+//! (?i)case insensitive
+//! (?w)whole word only
+//! (?iw)both at once
+//! re:colou?r
+//! (?i)re:\d{4}-\d{2}
+//! glob:run*
+//! ```
+//!
+//! Regular expressions and wildcards are taken verbatim, without processing any escape sequences.
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+
+use super::{Flags, Kind, Word};
+
+/// Error that occurred while parsing the words file. Line and column are 1-based.
+#[derive(Debug)]
+pub struct ParseError {
+    line: usize,
+    column: usize,
+    message: Cow<'static, str>,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "words file {}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+/// Parse all words together with the line they were found on.
+pub fn parse(input: &str) -> Result<Vec<(usize, Word<'_>)>, ParseError> {
+    input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|(i, line)| {
+            parse_line(line).map(|word| (i + 1, word)).map_err(|(column, message)| ParseError {
+                line: i + 1,
+                column,
+                message,
+            })
+        })
+        .collect()
+}
+
+/// Parse a single line, returning the failing column and a description on errors.
+fn parse_line(line: &str) -> Result<Word<'_>, (usize, Cow<'static, str>)> {
+    let mut flags = Flags::default();
+    let mut rest = line;
+    let mut column = 1;
+
+    if let Some(spec) = line.strip_prefix("(?") {
+        let end = spec.find(')').ok_or((1, "unterminated flag group".into()))?;
+        column += 2;
+
+        flags = Flags::parse(&spec[..end])
+            .map_err(|(i, c)| (column + i, format!("unknown flag `{c}`").into()))?;
+
+        column += spec[..end].chars().count() + 1;
+        rest = &spec[end + 1..];
+    }
+
+    if rest.is_empty() {
+        return Err((column, "empty pattern".into()));
+    }
+
+    for (prefix, kind) in [("re:", Kind::Regex), ("glob:", Kind::Glob)] {
+        if let Some(pattern) = rest.strip_prefix(prefix) {
+            let word = Word { kind, ..Word::new(Cow::Borrowed(pattern), flags) };
+            word.validate().map_err(|e| (column + prefix.len(), e.to_string().into()))?;
+            return Ok(word);
+        }
+    }
+
+    if !rest.contains('\\') {
+        return Ok(Word::new(Cow::Borrowed(rest), flags));
+    }
+
+    let mut text = String::with_capacity(rest.len());
+
+    while let Some(i) = rest.find('\\') {
+        text.push_str(&rest[..i]);
+        column += rest[..i].chars().count();
+
+        let (c, len) = unescape(&rest[i + 1..]).map_err(|msg| (column, msg))?;
+        text.push(c);
+        column += 1 + len;
+        rest = &rest[i + 1 + len..];
+    }
+
+    text.push_str(rest);
+
+    Ok(Word::new(Cow::Owned(text), flags))
+}
+
+/// Decode the escape sequence at the start of `s` (without the leading backslash), returning the
+/// character and the amount of bytes that were consumed. Escape sequences are pure ASCII, so this
+/// is the amount of characters as well.
+fn unescape(s: &str) -> Result<(char, usize), Cow<'static, str>> {
+    let c = s.chars().next().ok_or("incomplete escape sequence")?;
+
+    match c {
+        't' => Ok(('\t', 1)),
+        'n' => Ok(('\n', 1)),
+        'r' => Ok(('\r', 1)),
+        '\\' | '#' | '(' => Ok((c, 1)),
+        'x' => {
+            let hex = s.get(1..3).ok_or("`\\x` needs two hex digits")?;
+            // Parsing alone would accept a sign in place of the first digit.
+            if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(format!("invalid hex digits `{hex}`").into());
+            }
+            match u8::from_str_radix(hex, 16) {
+                Ok(b) if b.is_ascii() => Ok((b as char, 3)),
+                _ => Err("`\\x` escapes must be ASCII (00-7F), use `\\u{..}` instead".into()),
+            }
+        }
+        'u' => {
+            let end =
+                s.find('}').filter(|_| s[1..].starts_with('{')).ok_or("`\\u` needs braces")?;
+            Some(&s[2..end])
+                .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .and_then(char::from_u32)
+                .map(|c| (c, end + 1))
+                .ok_or_else(|| format!("invalid unicode escape `\\{}`", &s[..=end]).into())
+        }
+        _ => Err(format!("unknown escape sequence `\\{c}`").into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(line: &str) -> String {
+        parse_line(line).unwrap().text.into_owned()
+    }
+
+    fn error(input: &str) -> (usize, usize, String) {
+        let e = parse(input).unwrap_err();
+        (e.line, e.column, e.message.into_owned())
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(text(r"a\tb"), "a\tb");
+        assert_eq!(text(r"a\nb"), "a\nb");
+        assert_eq!(text(r"a\rb"), "a\rb");
+        assert_eq!(text(r"a\\b"), r"a\b");
+        assert_eq!(text(r"\#tag"), "#tag");
+        assert_eq!(text(r"\(?i)"), "(?i)");
+        assert_eq!(text(r"\x41\x7f"), "A\x7f");
+        assert_eq!(text(r"\u{e9}\u{1F600}"), "é😀");
+        assert_eq!(text(r"(?i)\x41b"), "Ab");
+
+        // Regular expressions and wildcards keep their backslashes.
+        assert_eq!(text(r"re:\d+"), r"\d+");
+        assert_eq!(text(r"glob:\*"), r"\*");
+    }
+
+    #[test]
+    fn invalid_escapes() {
+        for (line, message) in [
+            (r"a\", "incomplete escape sequence"),
+            (r"a\q", r"unknown escape sequence `\q`"),
+            (r"a\x4", r"`\x` needs two hex digits"),
+            (r"a\x4g", "invalid hex digits `4g`"),
+            (r"a\x80", r"`\x` escapes must be ASCII (00-7F), use `\u{..}` instead"),
+            (r"a\u41", r"`\u` needs braces"),
+            (r"a\u{}", r"invalid unicode escape `\u{}`"),
+            (r"a\u{d800}", r"invalid unicode escape `\u{d800}`"),
+            (r"a\u{110000}", r"invalid unicode escape `\u{110000}`"),
+        ] {
+            assert_eq!(error(line), (1, 2, message.to_owned()), "{line}");
+        }
+    }
+
+    #[test]
+    fn signs_in_escapes() {
+        assert_eq!(error(r"\x+1").2, "invalid hex digits `+1`");
+        assert_eq!(error(r"\x-1").2, "invalid hex digits `-1`");
+        assert_eq!(error(r"\u{+41}").2, r"invalid unicode escape `\u{+41}`");
+        assert_eq!(error(r"\u{-41}").2, r"invalid unicode escape `\u{-41}`");
+    }
+
+    #[test]
+    fn error_positions() {
+        // Columns count characters, not bytes.
+        assert_eq!(error("# comment\n\nok\nnaïve\\q").0, 4);
+        assert_eq!(error("naïve\\q").1, 6);
+        assert_eq!(error("😀\\t😀\\q").1, 5);
+        assert_eq!(error(r"é\u{e9}é\x4").1, 9);
+        assert_eq!(error("(?iz)a").1, 4);
+        assert_eq!(error("(?i)").1, 5);
+        assert_eq!(error("(?i").1, 1);
+    }
+}
@@ -0,0 +1,75 @@
+//! Wildcard patterns, translated into regular expressions.
+//!
+//! Wildcards never reach beyond a single word of the article:
+//!
+//! - `*` matches any number of word characters.
+//! - `?` matches exactly one word character.
+//! - `[ou]` matches one of the listed characters, `[a-z]` one of a range and `[!ou]` any word
+//!   character except the listed ones.
+//! - `\` takes the following character literally, like `\*` for a star.
+//!
+//! An opening bracket without a closing one is taken literally. A wildcard always matches whole
+//! words of the article, so `cat*` finds `cats`, but not `bobcats`.
+
+/// Translate a wildcard pattern into an equivalent regular expression. The expression is anchored
+/// to the start and end of a word, as it searches running text rather than single tokens.
+pub fn to_regex(glob: &str) -> String {
+    let mut re = String::with_capacity(glob.len() * 2);
+    re.push_str(r"\b{start-half}");
+    let mut rest = glob;
+
+    while let Some(c) = rest.chars().next() {
+        rest = &rest[c.len_utf8()..];
+
+        match c {
+            '*' => re.push_str(r"\w*"),
+            '?' => re.push_str(r"\w"),
+            '[' => match class(rest) {
+                Some((class, len)) => {
+                    re.push_str(&class);
+                    rest = &rest[len..];
+                }
+                None => re.push_str(r"\["),
+            },
+            '\\' => {
+                if let Some(c) = rest.chars().next() {
+                    rest = &rest[c.len_utf8()..];
+                    push_literal(&mut re, c);
+                } else {
+                    re.push_str(r"\\");
+                }
+            }
+            _ => push_literal(&mut re, c),
+        }
+    }
+
+    re.push_str(r"\b{end-half}");
+    re
+}
+
+/// Translate a character class, starting right after the opening bracket. Returns the class and
+/// the amount of bytes consumed, including the closing bracket.
+fn class(s: &str) -> Option<(String, usize)> {
+    let (negated, body) = match s.strip_prefix(['!', '^']) {
+        Some(body) => (true, body),
+        None => (false, s),
+    };
+
+    // A closing bracket right at the start is part of the class.
+    let end = body.char_indices().skip(1).find(|&(_, c)| c == ']').map(|(i, _)| i)?;
+
+    let mut class = String::from(if negated { r"[\w&&[^" } else { "[" });
+    for c in body[..end].chars() {
+        if matches!(c, '\\' | '[' | ']' | '&' | '~' | '^') {
+            class.push('\\');
+        }
+        class.push(c);
+    }
+    class.push_str(if negated { "]]" } else { "]" });
+
+    Some((class, s.len() - body.len() + end + 1))
+}
+
+fn push_literal(re: &mut String, c: char) {
+    re.push_str(&regex::escape(c.encode_utf8(&mut [0; 4])));
+}
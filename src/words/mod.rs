@@ -0,0 +1,272 @@
+//! Parsing of the words file into the list of patterns to search for.
+//!
+//! The default format takes every line verbatim, as required by the challenge. Besides that, an
+//! extended line based format with comments, escapes and flags is available, as well as structured
+//! formats (JSON, CSV and YAML) that can attach an id, a label and a group to each word.
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use indexmap::set::IndexSet;
+use regex::Regex;
+use serde::Deserialize;
+
+mod extended;
+mod glob;
+mod structured;
+
+/// Format of the words file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    /// One word per line, taken as is.
+    Plain,
+    /// Words with comments, escapes and flags.
+    Extended,
+    /// A JSON array of words or word records.
+    Json,
+    /// A CSV file with a header row and one word record per line.
+    Csv,
+    /// A YAML list of words or word records.
+    Yaml,
+}
+
+impl Format {
+    /// How the positions of entries in the words file are called in this format.
+    pub const fn unit(self) -> &'static str {
+        match self {
+            Self::Plain | Self::Extended => "line",
+            Self::Json | Self::Csv | Self::Yaml => "entry",
+        }
+    }
+
+    /// Guess the format from the extension of a file, falling back to [`Format::Plain`].
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        match path.as_ref().extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Self::Json,
+            Some("csv") => Self::Csv,
+            Some("yaml" | "yml") => Self::Yaml,
+            _ => Self::Plain,
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = UnknownFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(Self::Plain),
+            "extended" => Ok(Self::Extended),
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            "yaml" => Ok(Self::Yaml),
+            _ => Err(UnknownFormat(s.to_owned())),
+        }
+    }
+}
+
+/// Error returned when parsing an unsupported words file format.
+#[derive(Debug)]
+pub struct UnknownFormat(String);
+
+impl fmt::Display for UnknownFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown words format `{}`, expected one of `plain`, `extended`, `json`, `csv` or `yaml`",
+            self.0
+        )
+    }
+}
+
+impl Error for UnknownFormat {}
+
+/// Options that change how a single word is matched.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Flags {
+    /// Match regardless of case (`i`).
+    pub ignore_case: bool,
+    /// Only match if not surrounded by other word characters (`w`).
+    pub whole_word: bool,
+}
+
+impl Flags {
+    /// Parse flags from their single letter names, returning the position of the first unknown
+    /// flag on failure.
+    fn parse(s: &str) -> Result<Self, (usize, char)> {
+        let mut flags = Self::default();
+
+        for (i, c) in s.chars().enumerate() {
+            match c {
+                'i' => flags.ignore_case = true,
+                'w' => flags.whole_word = true,
+                _ => return Err((i, c)),
+            }
+        }
+
+        Ok(flags)
+    }
+}
+
+/// How the text of a word is interpreted.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    /// The text is searched for as it is.
+    #[default]
+    Literal,
+    /// The text is a regular expression.
+    Regex,
+    /// The text is a wildcard pattern.
+    Glob,
+}
+
+/// A distinct word to search for, as described by one or more entries of the words file.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Word<'a> {
+    pub text: Cow<'a, str>,
+    pub kind: Kind,
+    pub flags: Flags,
+    /// Identifier of the word, only available in structured formats.
+    pub id: Option<String>,
+    /// Display name of the word, only available in structured formats.
+    pub label: Option<String>,
+    /// Group or tag the word belongs to, only available in structured formats.
+    pub group: Option<String>,
+}
+
+impl<'a> Word<'a> {
+    pub const fn new(text: Cow<'a, str>, flags: Flags) -> Self {
+        Self { text, kind: Kind::Literal, flags, id: None, label: None, group: None }
+    }
+
+    /// The regular expression to search for, if this word isn't a literal.
+    pub fn regex(&self) -> Option<Cow<'_, str>> {
+        match self.kind {
+            Kind::Literal => None,
+            Kind::Regex => Some(Cow::Borrowed(&self.text)),
+            Kind::Glob => Some(Cow::Owned(glob::to_regex(&self.text))),
+        }
+    }
+
+    /// Check that the word can be searched for.
+    fn validate(&self) -> Result<(), regex::Error> {
+        self.regex().map_or(Ok(()), |re| Regex::new(&re).map(drop))
+    }
+}
+
+impl fmt::Display for Word<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(label) = &self.label {
+            return f.write_str(label);
+        }
+
+        if self.flags != Flags::default() {
+            f.write_str("(?")?;
+            if self.flags.ignore_case {
+                f.write_str("i")?;
+            }
+            if self.flags.whole_word {
+                f.write_str("w")?;
+            }
+            f.write_str(")")?;
+        }
+
+        match self.kind {
+            Kind::Literal => write!(f, "{}", self.text.escape_debug()),
+            Kind::Regex => write!(f, "re:{}", self.text),
+            Kind::Glob => write!(f, "glob:{}", self.text),
+        }
+    }
+}
+
+/// The content of a words file, with duplicate entries resolved to a single distinct word.
+pub struct WordList<'a> {
+    format: Format,
+    words: IndexSet<Word<'a>>,
+    entries: Vec<Entry>,
+}
+
+/// A single entry of the words file.
+#[derive(Clone, Copy, Debug)]
+pub struct Entry {
+    /// 1-based position within the file, see [`Format::unit`].
+    pub pos: usize,
+    /// Index of the distinct word this entry refers to.
+    pub word: usize,
+}
+
+impl<'a> WordList<'a> {
+    fn new(format: Format, words: Vec<(usize, Word<'a>)>) -> Self {
+        let mut set = IndexSet::with_capacity(words.len());
+        let entries = words
+            .into_iter()
+            .map(|(pos, word)| Entry { pos, word: set.insert_full(word).0 })
+            .collect();
+
+        Self { format, words: set, entries }
+    }
+
+    pub const fn format(&self) -> Format {
+        self.format
+    }
+
+    /// All distinct words, in the order of their first appearance.
+    pub const fn words(&self) -> &IndexSet<Word<'a>> {
+        &self.words
+    }
+
+    /// All entries of the file, in their original order.
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// The first entry of every distinct word.
+    pub fn distinct_entries(&self) -> impl Iterator<Item = &Entry> {
+        let mut seen = vec![false; self.words.len()];
+        self.entries.iter().filter(move |e| !std::mem::replace(&mut seen[e.word], true))
+    }
+
+    /// The distinct words that appear more than once, with the positions of all their entries.
+    pub fn duplicates(&self) -> Vec<(&Word<'a>, Vec<usize>)> {
+        let mut positions = vec![vec![]; self.words.len()];
+        for entry in &self.entries {
+            positions[entry.word].push(entry.pos);
+        }
+
+        self.words.iter().zip(positions).filter(|(_, pos)| pos.len() > 1).collect()
+    }
+
+    /// Positions of all entries with an empty word, which can never be found.
+    pub fn empty(&self) -> Vec<usize> {
+        self.entries.iter().filter(|e| self.words[e.word].text.is_empty()).map(|e| e.pos).collect()
+    }
+}
+
+/// Parse the content of a words file in the given format.
+pub fn parse(input: &str, format: Format) -> Result<WordList<'_>, Box<dyn Error>> {
+    let words = match format {
+        Format::Plain => input
+            .split_terminator('\n')
+            .enumerate()
+            .map(|(i, line)| (i + 1, Word::new(Cow::Borrowed(line), Flags::default())))
+            .collect(),
+        Format::Extended => extended::parse(input)?,
+        Format::Json => structured::parse_json(input)?,
+        Format::Csv => structured::parse_csv(input)?,
+        Format::Yaml => structured::parse_yaml(input)?,
+    };
+
+    Ok(WordList::new(format, words))
+}
+
+/// Check whether the match at `start..end` of `text` is not surrounded by other word characters.
+pub fn is_whole_word(text: &str, start: usize, end: usize) -> bool {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+
+    !text[..start].chars().next_back().is_some_and(is_word)
+        && !text[end..].chars().next().is_some_and(is_word)
+}
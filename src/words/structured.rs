@@ -0,0 +1,107 @@
+//! Structured words files in JSON, CSV or YAML.
+//!
+//! JSON and YAML files contain a list, where each entry is either a plain string or a record with
+//! further details about the word. CSV files need a header row naming the same fields. Unknown
+//! fields (or columns) are ignored.
+//!
+//! ```json
+//! [
+//!   "cat",
+//!   { "term": "dog", "id": 2, "label": "Dog", "group": "animals", "flags": "iw" },
+//!   { "term": "colou?r", "kind": "regex" },
+//!   { "term": "run*", "kind": "glob" }
+//! ]
+//! ```
+
+use std::borrow::Cow;
+use std::error::Error;
+
+use serde::Deserialize;
+
+use super::{Flags, Kind, Word};
+
+/// An item of the list in JSON and YAML files.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Item {
+    Term(String),
+    Record(Record),
+}
+
+#[derive(Deserialize)]
+struct Record {
+    #[serde(alias = "word", alias = "pattern")]
+    term: String,
+    id: Option<Id>,
+    label: Option<String>,
+    #[serde(alias = "category", alias = "tag")]
+    group: Option<String>,
+    #[serde(default)]
+    kind: Kind,
+    #[serde(default)]
+    flags: String,
+}
+
+/// Ids are kept as strings, but can be written as numbers as well.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Id {
+    Number(u64),
+    String(String),
+}
+
+pub fn parse_json(input: &str) -> Result<Vec<(usize, Word<'static>)>, Box<dyn Error>> {
+    let items: Vec<Item> = serde_json::from_str(input)?;
+    items.into_iter().enumerate().map(|(i, item)| convert(i + 1, item)).collect()
+}
+
+pub fn parse_yaml(input: &str) -> Result<Vec<(usize, Word<'static>)>, Box<dyn Error>> {
+    let items: Vec<Item> = serde_yaml::from_str(input)?;
+    items.into_iter().enumerate().map(|(i, item)| convert(i + 1, item)).collect()
+}
+
+pub fn parse_csv(input: &str) -> Result<Vec<(usize, Word<'static>)>, Box<dyn Error>> {
+    csv::Reader::from_reader(input.as_bytes())
+        .into_deserialize()
+        .enumerate()
+        .map(|(i, record)| convert(i + 1, Item::Record(record?)))
+        .collect()
+}
+
+/// Convert the item at the given 1-based position into a word.
+fn convert(pos: usize, item: Item) -> Result<(usize, Word<'static>), Box<dyn Error>> {
+    let record = match item {
+        Item::Term(term) => Record {
+            term,
+            id: None,
+            label: None,
+            group: None,
+            kind: Kind::Literal,
+            flags: String::new(),
+        },
+        Item::Record(record) => record,
+    };
+
+    if record.term.is_empty() {
+        return Err(format!("words entry {pos}: empty term").into());
+    }
+
+    let flags = Flags::parse(&record.flags)
+        .map_err(|(_, c)| format!("words entry {pos}: unknown flag `{c}`"))?;
+
+    let word = Word {
+        text: Cow::Owned(record.term),
+        kind: record.kind,
+        flags,
+        id: record.id.map(|id| match id {
+            Id::Number(n) => n.to_string(),
+            Id::String(s) => s,
+        }),
+        label: record.label,
+        group: record.group,
+    };
+
+    word.validate().map_err(|e| format!("words entry {pos}: {e}"))?;
+
+    Ok((pos, word))
+}